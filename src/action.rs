@@ -18,6 +18,6 @@ pub enum Action {
     InputEvent(InputEvent),
     // Run a command
     Command(Vec<String>),
-    // keypress_delay_ms
+    // keypress_delay_ms or { sleep: ... }
     Delay(Duration),
 }
@@ -1,5 +1,3 @@
-use std::thread;
-
 use evdev::{uinput::VirtualDevice, EventType, InputEvent, Key};
 use fork::{fork, setsid, Fork};
 use log::debug;
@@ -8,26 +6,67 @@ use nix::sys::signal;
 use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet};
 use std::process::{exit, Command, Stdio};
 
+use crate::action_queue::ActionQueue;
+use crate::clock::{Clock, Timer};
 use crate::event::RelativeEvent;
 use crate::{action::Action, event::KeyEvent};
 
 pub struct ActionDispatcher {
     // Device to emit events
     device: VirtualDevice,
+    // Actions waiting for an Action::Delay to pass, and the timer to resume them
+    queue: ActionQueue,
+    timer: Box<dyn Timer>,
+    clock: Box<dyn Clock>,
     // Whether we've called a sigaction for spawing commands or not
     sigaction_set: bool,
 }
 
 impl ActionDispatcher {
-    pub fn new(device: VirtualDevice) -> ActionDispatcher {
+    pub fn new(device: VirtualDevice, timer: Box<dyn Timer>, clock: Box<dyn Clock>) -> ActionDispatcher {
         ActionDispatcher {
             device,
+            queue: ActionQueue::default(),
+            timer,
+            clock,
             sigaction_set: false,
         }
     }
 
-    // Execute Actions created by EventHandler. This should be the only public method of ActionDispatcher.
+    // Execute Actions created by EventHandler. Actions after an Action::Delay are run by on_timeout() later.
     pub fn on_action(&mut self, action: Action) -> anyhow::Result<()> {
+        self.queue.push(action);
+        self.run_due_actions()
+    }
+
+    // Resume the actions after a delay. Called when the timer is readable.
+    pub fn on_timeout(&mut self) -> anyhow::Result<()> {
+        self.run_due_actions()
+    }
+
+    // Run all queued actions right away, e.g. before exiting
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        for action in self.queue.drain() {
+            self.run_action(action)?;
+        }
+        self.timer.set_deadline(None, self.clock.now())?;
+        Ok(())
+    }
+
+    fn run_due_actions(&mut self) -> anyhow::Result<()> {
+        let was_waiting = self.queue.deadline().is_some();
+        let now = self.clock.now();
+        for action in self.queue.pop_due(now) {
+            self.run_action(action)?;
+        }
+        // Avoid touching the timer for every key when nothing is delayed
+        if was_waiting || self.queue.deadline().is_some() {
+            self.timer.set_deadline(self.queue.deadline(), now)?;
+        }
+        Ok(())
+    }
+
+    fn run_action(&mut self, action: Action) -> anyhow::Result<()> {
         match action {
             Action::KeyEvent(key_event) => self.on_key_event(key_event)?,
             Action::RelativeEvent(relative_event) => self.on_relative_event(relative_event)?,
@@ -49,7 +88,8 @@ impl ActionDispatcher {
 
             Action::InputEvent(event) => self.send_event(event)?,
             Action::Command(command) => self.run_command(command),
-            Action::Delay(duration) => thread::sleep(duration),
+            // Waited for by the queue
+            Action::Delay(_) => {}
         }
         Ok(())
     }
@@ -0,0 +1,88 @@
+use crate::action::Action;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+// Actions held back by an Action::Delay until it passes, so that delays don't stop input from being read
+#[derive(Default)]
+pub struct ActionQueue {
+    actions: VecDeque<Action>,
+    // When the last popped delay ends
+    resume_at: Option<Instant>,
+}
+
+impl ActionQueue {
+    pub fn push(&mut self, action: Action) {
+        self.actions.push_back(action);
+    }
+
+    // Take the actions that can run at `now`, up to and including the next non-zero delay
+    pub fn pop_due(&mut self, now: Instant) -> Vec<Action> {
+        let mut due = vec![];
+        if self.resume_at.is_some_and(|resume_at| now < resume_at) {
+            return due;
+        }
+        self.resume_at = None;
+        while let Some(action) = self.actions.pop_front() {
+            if let Action::Delay(duration) = action {
+                if !duration.is_zero() {
+                    self.resume_at = Some(now + duration);
+                    due.push(action);
+                    break;
+                }
+            }
+            due.push(action);
+        }
+        due
+    }
+
+    // When pop_due() should be called again, if anything is waiting for a delay
+    pub fn deadline(&self) -> Option<Instant> {
+        self.resume_at
+    }
+
+    // Take everything left, ignoring delays
+    pub fn drain(&mut self) -> Vec<Action> {
+        self.resume_at = None;
+        self.actions.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{KeyEvent, KeyValue};
+    use evdev::Key;
+    use std::time::Duration;
+
+    #[test]
+    fn test_pop_due() {
+        let key = |key| Action::KeyEvent(KeyEvent::new(key, KeyValue::Press));
+        let start = Instant::now();
+        let mut queue = ActionQueue::default();
+        queue.push(key(Key::KEY_A));
+        queue.push(Action::Delay(Duration::ZERO));
+        queue.push(key(Key::KEY_B));
+        queue.push(Action::Delay(Duration::from_millis(10)));
+        queue.push(key(Key::KEY_C));
+        assert_eq!(
+            "[KeyEvent(KeyEvent { key: KEY_A, value: Press }), Delay(0ns), KeyEvent(KeyEvent { key: KEY_B, value: Press }), Delay(10ms)]",
+            format!("{:?}", queue.pop_due(start))
+        );
+        assert_eq!(Some(start + Duration::from_millis(10)), queue.deadline());
+
+        // Actions pushed during a delay wait behind it
+        queue.push(key(Key::KEY_D));
+        assert_eq!("[]", format!("{:?}", queue.pop_due(start + Duration::from_millis(9))));
+        assert_eq!(
+            "[KeyEvent(KeyEvent { key: KEY_C, value: Press }), KeyEvent(KeyEvent { key: KEY_D, value: Press })]",
+            format!("{:?}", queue.pop_due(start + Duration::from_millis(10)))
+        );
+        assert_eq!(None, queue.deadline());
+
+        queue.push(Action::Delay(Duration::from_millis(10)));
+        queue.push(key(Key::KEY_E));
+        queue.pop_due(start);
+        assert_eq!("[KeyEvent(KeyEvent { key: KEY_E, value: Press })]", format!("{:?}", queue.drain()));
+        assert_eq!(None, queue.deadline());
+    }
+}
@@ -33,18 +33,8 @@ impl Client for GnomeClient {
         };
 
         // Attempt the latest protocol
-        if let Ok(message) = connection.call_method(
-            Some("org.gnome.Shell"),
-            "/com/k0kubun/Xremap",
-            Some("com.k0kubun.Xremap"),
-            "ActiveWindow",
-            &(),
-        ) {
-            if let Ok(json) = message.body::<String>() {
-                if let Ok(window) = serde_json::from_str::<ActiveWindow>(&json) {
-                    return Some(window.wm_class);
-                }
-            }
+        if let Some(window) = active_window(connection) {
+            return Some(window.wm_class);
         // Fallback to the legacy protocol
         } else if let Ok(message) = connection.call_method(
             Some("org.gnome.Shell"),
@@ -59,6 +49,25 @@ impl Client for GnomeClient {
         }
         None
     }
+
+    fn current_window(&mut self) -> Option<String> {
+        self.connect();
+        let connection = match &mut self.connection {
+            Some(connection) => connection,
+            None => return None,
+        };
+
+        // The legacy protocol doesn't support titles
+        active_window(connection).map(|window| window.title)
+    }
+}
+
+fn active_window(connection: &Connection) -> Option<ActiveWindow> {
+    let message = connection
+        .call_method(Some("org.gnome.Shell"), "/com/k0kubun/Xremap", Some("com.k0kubun.Xremap"), "ActiveWindow", &())
+        .ok()?;
+    let json = message.body::<String>().ok()?;
+    serde_json::from_str::<ActiveWindow>(&json).ok()
 }
 
 #[derive(Serialize, Deserialize)]
@@ -15,14 +15,21 @@ impl Client for HyprlandClient {
     }
 
     fn current_application(&mut self) -> Option<String> {
-        if let Ok(win) = get_active_window() {
-            let s = serde_json::to_string(&win).ok()?;
-            let v: Value = serde_json::from_str(&s).ok()?;
-            let app = v["class"].as_str();
-            if let Some(app) = app {
-                return Some(String::from(app));
-            }
+        get_active_window_property("class")
+    }
+
+    fn current_window(&mut self) -> Option<String> {
+        get_active_window_property("title")
+    }
+}
+
+fn get_active_window_property(property: &str) -> Option<String> {
+    if let Ok(win) = get_active_window() {
+        let s = serde_json::to_string(&win).ok()?;
+        let v: Value = serde_json::from_str(&s).ok()?;
+        if let Some(value) = v[property].as_str() {
+            return Some(String::from(value));
         }
-        None
     }
+    None
 }
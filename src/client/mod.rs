@@ -1,6 +1,7 @@
 pub trait Client {
     fn supported(&mut self) -> bool;
     fn current_application(&mut self) -> Option<String>;
+    fn current_window(&mut self) -> Option<String>;
 }
 
 pub struct WMClient {
@@ -8,6 +9,7 @@ pub struct WMClient {
     client: Box<dyn Client>,
     supported: Option<bool>,
     last_application: String,
+    last_window: String,
 }
 
 impl WMClient {
@@ -17,16 +19,17 @@ impl WMClient {
             client,
             supported: None,
             last_application: String::new(),
+            last_window: String::new(),
         }
     }
 
+    // The application found by the last current_application(), if any
+    pub fn last_application(&self) -> Option<&str> {
+        Some(self.last_application.as_str()).filter(|application| !application.is_empty())
+    }
+
     pub fn current_application(&mut self) -> Option<String> {
-        if self.supported.is_none() {
-            let supported = self.client.supported();
-            self.supported = Some(supported);
-            println!("application-client: {} (supported: {})", self.name, supported);
-        }
-        if !self.supported.unwrap() {
+        if !self.supported() {
             return None;
         }
 
@@ -39,6 +42,30 @@ impl WMClient {
         }
         result
     }
+
+    pub fn current_window(&mut self) -> Option<String> {
+        if !self.supported() {
+            return None;
+        }
+
+        let result = self.client.current_window();
+        if let Some(window) = &result {
+            if &self.last_window != window {
+                self.last_window = window.clone();
+                println!("window: {}", window);
+            }
+        }
+        result
+    }
+
+    fn supported(&mut self) -> bool {
+        if self.supported.is_none() {
+            let supported = self.client.supported();
+            self.supported = Some(supported);
+            println!("application-client: {} (supported: {})", self.name, supported);
+        }
+        self.supported.unwrap()
+    }
 }
 
 #[cfg(feature = "gnome")]
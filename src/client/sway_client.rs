@@ -59,6 +59,21 @@ impl Client for SwayClient {
         }
         None
     }
+
+    fn current_window(&mut self) -> Option<String> {
+        self.connect();
+        let connection = match &mut self.connection {
+            Some(connection) => connection,
+            None => return None,
+        };
+
+        if let Ok(node) = connection.get_tree() {
+            if let Some(node) = node.find_focused(|n| n.focused) {
+                return node.name;
+            }
+        }
+        None
+    }
 }
 
 // e.g. "/run/user/1000/sway-ipc.1000.2575.sock"
@@ -3,18 +3,23 @@ use anyhow::bail;
 use std::env;
 use x11rb::cookie::Cookie;
 use x11rb::protocol::xproto::{self};
-use x11rb::protocol::xproto::{AtomEnum, Window};
+use x11rb::protocol::xproto::{Atom, AtomEnum, Window};
 use x11rb::rust_connection::ConnectionError;
 use x11rb::x11_utils::TryParse;
 use x11rb::{protocol::xproto::get_property, rust_connection::RustConnection};
 
 pub struct X11Client {
     connection: Option<RustConnection>,
+    // Interned once per connection since window titles are looked up on key events
+    net_wm_name: Option<Atom>,
 }
 
 impl X11Client {
     pub fn new() -> X11Client {
-        X11Client { connection: None }
+        X11Client {
+            connection: None,
+            net_wm_name: None,
+        }
     }
 
     fn connect(&mut self) {
@@ -30,6 +35,7 @@ impl X11Client {
     }
 
     fn reconnect(&mut self) {
+        self.net_wm_name = None;
         match x11rb::connect(None) {
             Ok((connection, _)) => self.connection = Some(connection),
             Err(error) => {
@@ -63,6 +69,18 @@ impl Client for X11Client {
             window = get_parent_window(self, window)?;
         }
     }
+
+    fn current_window(&mut self) -> Option<String> {
+        self.connect();
+        let mut window = get_focus_window(self)?;
+        loop {
+            if let Some(title) = get_net_wm_name(self, window).or_else(|| get_wm_name(self, window)) {
+                return Some(title);
+            }
+
+            window = get_parent_window(self, window)?;
+        }
+    }
 }
 
 fn get_focus_window(client: &mut X11Client) -> Option<Window> {
@@ -102,6 +120,38 @@ fn get_wm_class(client: &mut X11Client, window: Window) -> Option<String> {
     return None;
 }
 
+// UTF-8 title set by EWMH-compliant clients
+fn get_net_wm_name(client: &mut X11Client, window: Window) -> Option<String> {
+    let atom = match client.net_wm_name {
+        Some(atom) => atom,
+        None => {
+            let atom =
+                get_cookie_reply_with_reconnect(client, |conn| xproto::intern_atom(conn, false, b"_NET_WM_NAME"))
+                    .ok()?
+                    .atom;
+            client.net_wm_name = Some(atom);
+            atom
+        }
+    };
+    get_string_property(client, window, atom)
+}
+
+fn get_wm_name(client: &mut X11Client, window: Window) -> Option<String> {
+    get_string_property(client, window, AtomEnum::WM_NAME.into())
+}
+
+fn get_string_property(client: &mut X11Client, window: Window, property: Atom) -> Option<String> {
+    let reply = get_cookie_reply_with_reconnect(client, |conn| {
+        get_property(conn, false, window, property, AtomEnum::ANY, 0, 1024)
+    })
+    .ok()?;
+
+    if reply.value.is_empty() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&reply.value).into_owned())
+}
+
 fn get_cookie_reply_with_reconnect<T: TryParse>(
     client: &mut X11Client,
     get_cookie: impl Fn(&RustConnection) -> Result<Cookie<RustConnection, T>, ConnectionError>,
@@ -0,0 +1,92 @@
+use nix::sys::time::TimeSpec;
+use nix::sys::timerfd::{Expiration, TimerFd, TimerSetTimeFlags};
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+// Where EventHandler and ActionDispatcher read the time from, so that tests and replays can control it
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+// A clock that only moves when it's told to. Clones share the same time.
+#[derive(Clone)]
+pub struct ManualClock {
+    start: Instant,
+    now: Rc<Cell<Instant>>,
+}
+
+impl ManualClock {
+    pub fn new() -> ManualClock {
+        let start = Instant::now();
+        ManualClock {
+            start,
+            now: Rc::new(Cell::new(start)),
+        }
+    }
+
+    // Time since the clock was created
+    pub fn elapsed(&self) -> Duration {
+        self.now.get() - self.start
+    }
+
+    pub fn set_elapsed(&self, elapsed: Duration) {
+        self.now.set(self.start + elapsed);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+}
+
+// Wakes up the event loop when a timeout of EventHandler or a delay of ActionDispatcher is reached
+pub trait Timer {
+    // Fire at `deadline`, or never if it's None. `now` is the time on the owner's clock.
+    fn set_deadline(&mut self, deadline: Option<Instant>, now: Instant) -> nix::Result<()>;
+}
+
+// Readable through select(2) on expiration
+impl Timer for TimerFd {
+    fn set_deadline(&mut self, deadline: Option<Instant>, now: Instant) -> nix::Result<()> {
+        self.unset()?;
+        if let Some(deadline) = deadline {
+            // A zero expiration would disarm the timer
+            let timeout = deadline.saturating_duration_since(now).max(Duration::from_nanos(1));
+            self.set(Expiration::OneShot(TimeSpec::from_duration(timeout)), TimerSetTimeFlags::empty())?;
+        }
+        Ok(())
+    }
+}
+
+// A timer that only remembers its deadline, for whoever drives a ManualClock. Clones share the deadline.
+#[derive(Clone, Default)]
+pub struct ManualTimer {
+    deadline: Rc<Cell<Option<Instant>>>,
+}
+
+impl ManualTimer {
+    pub fn new() -> ManualTimer {
+        ManualTimer::default()
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline.get()
+    }
+}
+
+impl Timer for ManualTimer {
+    fn set_deadline(&mut self, deadline: Option<Instant>, _now: Instant) -> nix::Result<()> {
+        self.deadline.set(deadline);
+        Ok(())
+    }
+}
@@ -5,7 +5,7 @@ use regex::Regex;
 use serde::{Deserialize, Deserializer};
 
 // TODO: Use trait to allow only either `only` or `not`
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 #[serde(deny_unknown_fields)]
 pub struct Application {
     #[serde(default, deserialize_with = "deserialize_matchers")]
@@ -24,6 +24,18 @@ pub enum ApplicationMatcher {
     Regex(Regex),
 }
 
+// Regex doesn't implement PartialEq, so regexes are compared by their patterns
+impl PartialEq for ApplicationMatcher {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (ApplicationMatcher::Literal(a), ApplicationMatcher::Literal(b)) => a == b,
+            (ApplicationMatcher::Name(a), ApplicationMatcher::Name(b)) => a == b,
+            (ApplicationMatcher::Regex(a), ApplicationMatcher::Regex(b)) => a.as_str() == b.as_str(),
+            _ => false,
+        }
+    }
+}
+
 impl ApplicationMatcher {
     pub fn matches(&self, app: &str) -> bool {
         match &self {
@@ -85,7 +97,7 @@ fn slash_unescape(s: &str) -> anyhow::Result<String> {
     Ok(result)
 }
 
-fn deserialize_matchers<'de, D>(deserializer: D) -> Result<Option<Vec<ApplicationMatcher>>, D::Error>
+pub fn deserialize_matchers<'de, D>(deserializer: D) -> Result<Option<Vec<ApplicationMatcher>>, D::Error>
 where
     D: Deserializer<'de>,
 {
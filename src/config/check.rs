@@ -0,0 +1,198 @@
+use crate::config::key_press::{same_key_press, KeyPress};
+use crate::config::keymap::Keymap;
+use crate::config::keymap_action::KeymapAction;
+use crate::config::leader::LeaderNode;
+use crate::config::modmap_action::{ModmapAction, PressReleaseKey, TapDance, TapDanceHold};
+use crate::config::remap::Remap;
+use crate::config::{load_config_file, merge_configs, Config};
+use std::path::PathBuf;
+
+// Validate config files for `--check` without opening any device. Errors are printed per file,
+// and warnings about semantic problems are printed if all files are loaded. Return false on errors.
+pub fn check_configs(filenames: &[PathBuf]) -> bool {
+    let mut configs = vec![];
+    let mut errors = 0;
+    for filename in filenames {
+        match load_config_file(filename, &mut vec![]) {
+            Ok(config) => configs.push(config),
+            Err(e) => {
+                println!("error: {}", e);
+                errors += 1;
+            }
+        }
+    }
+    if errors > 0 {
+        println!("{} error(s) found", errors);
+        return false;
+    }
+
+    let config = match merge_configs(configs) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("error: {}", e);
+            return false;
+        }
+    };
+    let warnings = check_config(&config);
+    for warning in &warnings {
+        println!("warning: {}", warning);
+    }
+    println!("Config is valid ({} warning(s))", warnings.len());
+    true
+}
+
+// Find problems that don't fail deserialization but are likely mistakes
+pub fn check_config(config: &Config) -> Vec<String> {
+    let mut warnings = vec![];
+    let mut actions: Vec<&KeymapAction> = vec![];
+    for modmap in &config.modmap {
+        for action in modmap.remap.values() {
+            match action {
+                ModmapAction::PressReleaseKey(PressReleaseKey { press, release }) => {
+                    collect_actions(press, &mut actions);
+                    collect_actions(release, &mut actions);
+                }
+                ModmapAction::TapDance(TapDance { taps, hold, .. }) => {
+                    for tap_actions in taps {
+                        collect_actions(tap_actions, &mut actions);
+                    }
+                    if let Some(TapDanceHold::Actions(hold_actions)) = hold {
+                        collect_actions(hold_actions, &mut actions);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    for keymap in &config.keymap {
+        for keymap_actions in keymap.remap.values() {
+            collect_actions(keymap_actions, &mut actions);
+        }
+    }
+    for combo in &config.combo {
+        collect_actions(&combo.actions, &mut actions);
+    }
+    if let Some(leader) = &config.leader {
+        collect_leader_actions(&leader.sequences, &mut actions);
+    }
+
+    let modes = config.modes();
+    // Layers that have keymaps
+    let layers: Vec<&str> = config
+        .keymap
+        .iter()
+        .filter_map(|keymap| keymap.layer.as_deref())
+        .collect();
+    for action in &actions {
+        match action {
+            KeymapAction::SetMode(mode) if !modes.contains(&mode.as_str()) => {
+                warnings.push(format!("set_mode: mode '{}' is not used by any keymap", mode));
+            }
+            KeymapAction::LayerHold(layer) if !layers.contains(&layer.as_str()) => {
+                warnings.push(format!("layer_hold: layer '{}' is not used by any keymap", layer));
+            }
+            KeymapAction::LayerToggle(layer) if !layers.contains(&layer.as_str()) => {
+                warnings.push(format!("layer_toggle: layer '{}' is not used by any keymap", layer));
+            }
+            KeymapAction::LayerOneshot(layer) if !layers.contains(&layer.as_str()) => {
+                warnings.push(format!("layer_oneshot: layer '{}' is not used by any keymap", layer));
+            }
+            KeymapAction::Remap(Remap {
+                timeout: None,
+                timeout_key: Some(key),
+                ..
+            }) => {
+                warnings.push(format!("remap: timeout_key '{:?}' is never used since timeout_millis is not set", key));
+            }
+            KeymapAction::Repeat(repeat) if repeat.times == 0 => {
+                warnings.push("repeat: actions are never run since times is 0".to_string());
+            }
+            _ => {}
+        }
+    }
+
+    for (index, keymap) in config.keymap.iter().enumerate() {
+        for (key_press, keymap_actions) in &keymap.remap {
+            if is_remap(keymap_actions) {
+                continue; // nested remaps are merged instead of shadowed
+            }
+            let shadowing = config.keymap[..index].iter().enumerate().find(|(_, earlier)| {
+                shadows(earlier, keymap)
+                    && earlier.remap.iter().any(|(earlier_key_press, actions)| {
+                        same_key_press(earlier_key_press, key_press) && !is_remap(actions)
+                    })
+            });
+            if let Some((earlier_index, earlier)) = shadowing {
+                warnings.push(format!(
+                    "keymap {}: {} is unreachable because keymap {} matches it first",
+                    keymap_name(keymap, index),
+                    key_press_name(key_press),
+                    keymap_name(earlier, earlier_index),
+                ));
+            }
+        }
+    }
+    warnings
+}
+
+fn collect_actions<'a>(actions: &'a [KeymapAction], result: &mut Vec<&'a KeymapAction>) {
+    for action in actions {
+        result.push(action);
+        match action {
+            KeymapAction::Remap(remap) => {
+                for nested_actions in remap.remap.values() {
+                    collect_actions(nested_actions, result);
+                }
+            }
+            KeymapAction::Repeat(repeat) => collect_actions(&repeat.actions, result),
+            _ => {}
+        }
+    }
+}
+
+fn collect_leader_actions<'a>(node: &'a LeaderNode, result: &mut Vec<&'a KeymapAction>) {
+    if let Some(actions) = &node.actions {
+        collect_actions(actions, result);
+    }
+    for (_, child) in &node.children {
+        collect_leader_actions(child, result);
+    }
+}
+
+fn is_remap(actions: &[KeymapAction]) -> bool {
+    actions.iter().all(|action| matches!(action, KeymapAction::Remap(..)))
+}
+
+// Whether the earlier keymap is active whenever the later one is
+fn shadows(earlier: &Keymap, later: &Keymap) -> bool {
+    // An exact_match keymap doesn't match key presses with extra modifiers
+    if earlier.exact_match && !later.exact_match {
+        return false;
+    }
+    // Keymaps of different layers are looked up separately
+    if earlier.layer != later.layer {
+        return false;
+    }
+    (earlier.application.is_none() || earlier.application == later.application)
+        && (earlier.window.is_none() || earlier.window == later.window)
+        && (earlier.device.is_none() || earlier.device == later.device)
+        && (earlier.mode.is_none() || earlier.mode == later.mode)
+        && (earlier.when.is_none() || earlier.when == later.when)
+}
+
+fn keymap_name(keymap: &Keymap, index: usize) -> String {
+    if keymap.name.is_empty() {
+        format!("#{}", index)
+    } else {
+        format!("'{}'", keymap.name)
+    }
+}
+
+pub fn key_press_name(key_press: &KeyPress) -> String {
+    let mut name = String::new();
+    for modifier in &key_press.modifiers {
+        name.push_str(&format!("{:?}-", modifier));
+    }
+    name.push_str(&format!("{:?}", key_press.key));
+    name
+}
@@ -0,0 +1,42 @@
+use crate::config::key::parse_key;
+use crate::config::keymap_action::KeymapAction;
+use crate::config::modmap_action::deserialize_actions;
+use evdev::Key;
+use serde::{de, Deserialize, Deserializer};
+use serde_with::{serde_as, DurationMilliSeconds};
+use std::time::Duration;
+
+// Keys pressed together within a timeout, e.g. `{ keys: [j, k], actions: Esc }`
+#[serde_as]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Combo {
+    #[serde(deserialize_with = "deserialize_keys")]
+    pub keys: Vec<Key>,
+    #[serde(deserialize_with = "deserialize_actions")]
+    pub actions: Vec<KeymapAction>,
+    #[serde_as(as = "DurationMilliSeconds")]
+    #[serde(default = "default_timeout", rename = "timeout_millis")]
+    pub timeout: Duration,
+}
+
+fn deserialize_keys<'de, D>(deserializer: D) -> Result<Vec<Key>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut keys: Vec<Key> = vec![];
+    for key in Vec::<String>::deserialize(deserializer)? {
+        let key = parse_key(&key).map_err(de::Error::custom)?;
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+    if keys.len() < 2 {
+        return Err(de::Error::custom("a combo needs at least two different keys"));
+    }
+    Ok(keys)
+}
+
+fn default_timeout() -> Duration {
+    Duration::from_millis(50)
+}
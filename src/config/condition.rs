@@ -0,0 +1,105 @@
+use crate::config::application::{deserialize_matchers, deserialize_string_or_vec, Application};
+use crate::config::device::Device;
+use crate::config::key::parse_key;
+use evdev::Key;
+use serde::{Deserialize, Deserializer};
+
+// `when:` expression for modmap and keymap, combining conditions that are otherwise ANDed
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+    // All conditions are true
+    All(Vec<Condition>),
+    // At least one condition is true
+    Any(Vec<Condition>),
+    // The condition is false
+    Not(Box<Condition>),
+    // The application name matches any of them
+    Application(Application),
+    // The window title matches any of them
+    Window(Application),
+    // The current mode is any of them
+    Mode(Vec<String>),
+    // The event comes from a device matching any of them
+    Device(Device),
+    // All of the keys are held
+    Held(Vec<Key>),
+}
+
+// A map with a single key like `{ mode: normal }`. This is used instead of serde's externally tagged enum
+// since serde_yaml expects a YAML tag like `!mode normal` for that.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConditionMap {
+    all: Option<Vec<Condition>>,
+    any: Option<Vec<Condition>>,
+    not: Option<Box<Condition>>,
+    #[serde(default, deserialize_with = "deserialize_application")]
+    application: Option<Application>,
+    #[serde(default, deserialize_with = "deserialize_application")]
+    window: Option<Application>,
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    mode: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "deserialize_device")]
+    device: Option<Device>,
+    #[serde(default, deserialize_with = "deserialize_keys")]
+    held: Option<Vec<Key>>,
+}
+
+impl<'de> Deserialize<'de> for Condition {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let map = ConditionMap::deserialize(deserializer)?;
+        let conditions: Vec<Condition> = [
+            map.all.map(Condition::All),
+            map.any.map(Condition::Any),
+            map.not.map(Condition::Not),
+            map.application.map(Condition::Application),
+            map.window.map(Condition::Window),
+            map.mode.map(Condition::Mode),
+            map.device.map(Condition::Device),
+            map.held.map(Condition::Held),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        match <[Condition; 1]>::try_from(conditions) {
+            Ok([condition]) => Ok(condition),
+            Err(_) => Err(serde::de::Error::custom(
+                "expected a map with exactly one of all, any, not, application, window, mode, device, or held",
+            )),
+        }
+    }
+}
+
+fn deserialize_application<'de, D>(deserializer: D) -> Result<Option<Application>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Some(Application {
+        only: deserialize_matchers(deserializer)?,
+        not: None,
+    }))
+}
+
+fn deserialize_device<'de, D>(deserializer: D) -> Result<Option<Device>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Some(Device {
+        only: deserialize_string_or_vec(deserializer)?,
+        not: None,
+    }))
+}
+
+fn deserialize_keys<'de, D>(deserializer: D) -> Result<Option<Vec<Key>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut keys = vec![];
+    for key in deserialize_string_or_vec(deserializer)?.unwrap_or_default() {
+        keys.push(parse_key(&key).map_err(serde::de::Error::custom)?);
+    }
+    Ok(Some(keys))
+}
@@ -0,0 +1,25 @@
+use crate::config::application::deserialize_string_or_vec;
+use crate::device::InputDeviceInfo;
+use serde::Deserialize;
+
+// Limit modmap/keymap to events from devices matching names or paths like --device
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Device {
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub only: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub not: Option<Vec<String>>,
+}
+
+impl Device {
+    pub fn matches(&self, device: &InputDeviceInfo) -> bool {
+        if let Some(only) = &self.only {
+            return device.matches(only);
+        }
+        if let Some(not) = &self.not {
+            return !device.matches(not);
+        }
+        true
+    }
+}
@@ -0,0 +1,131 @@
+use crate::config::leader::LeaderNode;
+use crate::config::Config;
+use std::path::PathBuf;
+
+// Summarize what a reload changes, e.g. `keymap: 2 -> 3 entries, added "Vim"`.
+// Entries are told apart by their names, so edits inside an entry aren't reported.
+pub fn config_changes(old: &Config, new: &Config) -> Vec<String> {
+    let mut changes = vec![];
+    let modmap_names = |config: &Config| config.modmap.iter().map(|modmap| modmap.name.clone()).collect();
+    let keymap_names = |config: &Config| config.keymap.iter().map(|keymap| keymap.name.clone()).collect();
+    changes.extend(entry_changes("modmap", modmap_names(old), modmap_names(new)));
+    changes.extend(entry_changes("keymap", keymap_names(old), keymap_names(new)));
+    let combo_names = |config: &Config| {
+        config
+            .combo
+            .iter()
+            .map(|combo| {
+                combo
+                    .keys
+                    .iter()
+                    .map(|key| format!("{:?}", key))
+                    .collect::<Vec<_>>()
+                    .join("+")
+            })
+            .collect()
+    };
+    changes.extend(entry_changes("combo", combo_names(old), combo_names(new)));
+
+    let leader_sequences = |config: &Config| {
+        config
+            .leader
+            .as_ref()
+            .map_or(0, |leader| count_sequences(&leader.sequences))
+    };
+    if leader_sequences(old) != leader_sequences(new) {
+        changes.push(format!("leader: {} -> {} sequences", leader_sequences(old), leader_sequences(new)));
+    }
+    if old.default_mode() != new.default_mode() {
+        changes.push(format!("default_mode: {} -> {}", old.default_mode(), new.default_mode()));
+    }
+    if old.keypress_delay() != new.keypress_delay() {
+        changes.push(format!(
+            "keypress_delay_ms: {} -> {}",
+            old.keypress_delay().as_millis(),
+            new.keypress_delay().as_millis()
+        ));
+    }
+    if old.virtual_modifiers != new.virtual_modifiers {
+        changes.push(format!("virtual_modifiers: {:?} -> {:?}", old.virtual_modifiers, new.virtual_modifiers));
+    }
+    let file_names = |files: &Vec<PathBuf>| files.iter().map(|file| file.display().to_string()).collect();
+    changes.extend(entry_changes("files", file_names(&old.config_files), file_names(&new.config_files)));
+    changes
+}
+
+// Report a section if its number of entries or their names changed. Unnamed entries are only counted.
+fn entry_changes(section: &str, old: Vec<String>, new: Vec<String>) -> Option<String> {
+    let added: Vec<String> = new
+        .iter()
+        .filter(|name| !name.is_empty() && !old.contains(name))
+        .map(|name| format!("{:?}", name))
+        .collect();
+    let removed: Vec<String> = old
+        .iter()
+        .filter(|name| !name.is_empty() && !new.contains(name))
+        .map(|name| format!("{:?}", name))
+        .collect();
+    if old.len() == new.len() && added.is_empty() && removed.is_empty() {
+        return None;
+    }
+    let mut change = format!("{}: {} -> {} entries", section, old.len(), new.len());
+    if !added.is_empty() {
+        change.push_str(&format!(", added {}", added.join(" ")));
+    }
+    if !removed.is_empty() {
+        change.push_str(&format!(", removed {}", removed.join(" ")));
+    }
+    Some(change)
+}
+
+fn count_sequences(node: &LeaderNode) -> usize {
+    let sequences: usize = node.children.iter().map(|(_, child)| count_sequences(child)).sum();
+    sequences + usize::from(node.actions.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_config_changes() {
+        let old: Config = serde_yaml::from_str(indoc! {"
+            keymap:
+              - name: Emacs
+                remap:
+                  C-b: left
+              - remap:
+                  C-f: right
+            combo:
+              - keys: [J, K]
+                actions: Esc
+        "})
+        .unwrap();
+        let new: Config = serde_yaml::from_str(indoc! {"
+            default_mode: normal
+            keymap:
+              - name: Vim
+                remap:
+                  H: left
+              - remap:
+                  C-f: right
+              - remap:
+                  C-n: down
+            leader:
+              sequences:
+                G G: C-Home
+        "})
+        .unwrap();
+        assert_eq!(
+            vec![
+                r#"keymap: 2 -> 3 entries, added "Vim", removed "Emacs""#,
+                r#"combo: 1 -> 0 entries, removed "KEY_J+KEY_K""#,
+                "leader: 0 -> 1 sequences",
+                "default_mode: default -> normal",
+            ],
+            config_changes(&old, &new)
+        );
+        assert_eq!(Vec::<String>::new(), config_changes(&new, &new));
+    }
+}
@@ -20,6 +20,13 @@ pub enum Modifier {
     Key(Key),
 }
 
+// Whether the key presses are the same regardless of the order of modifiers, e.g. `C-M-a` and `M-C-a`
+pub fn same_key_press(a: &KeyPress, b: &KeyPress) -> bool {
+    a.key == b.key
+        && a.modifiers.len() == b.modifiers.len()
+        && a.modifiers.iter().all(|modifier| b.modifiers.contains(modifier))
+}
+
 impl<'de> Deserialize<'de> for KeyPress {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -30,7 +37,7 @@ impl<'de> Deserialize<'de> for KeyPress {
     }
 }
 
-fn parse_key_press(input: &str) -> Result<KeyPress, Box<dyn error::Error>> {
+pub fn parse_key_press(input: &str) -> Result<KeyPress, Box<dyn error::Error>> {
     let keys: Vec<&str> = input.split('-').collect();
     if let Some((key, modifier_keys)) = keys.split_last() {
         let mut modifiers = vec![];
@@ -52,21 +59,26 @@ fn parse_key_press(input: &str) -> Result<KeyPress, Box<dyn error::Error>> {
 
 fn parse_modifier(modifier: &str) -> Result<Modifier, Box<dyn Error>> {
     // Everything is case-insensitive
-    match &modifier.to_uppercase()[..] {
-        // Shift
-        "SHIFT" => Ok(Modifier::Shift),
-        // Control
-        "C" => Ok(Modifier::Control),
-        "CTRL" => Ok(Modifier::Control),
-        "CONTROL" => Ok(Modifier::Control),
-        // Alt
-        "M" => Ok(Modifier::Alt),
-        "ALT" => Ok(Modifier::Alt),
-        // Windows
-        "SUPER" => Ok(Modifier::Windows),
-        "WIN" => Ok(Modifier::Windows),
-        "WINDOWS" => Ok(Modifier::Windows),
-        // else
-        key => parse_key(key).map(|key| Modifier::Key(key)),
+    let name = modifier.to_uppercase();
+    match MODIFIER_ALIASES.iter().find(|(alias, _)| *alias == name) {
+        Some((_, modifier)) => Ok(modifier.clone()),
+        None => parse_key(&name).map(Modifier::Key),
     }
 }
+
+// Prefixes accepted by parse_modifier, case-insensitively. Any key name is also accepted.
+pub static MODIFIER_ALIASES: &[(&str, Modifier)] = &[
+    // Shift
+    ("SHIFT", Modifier::Shift),
+    // Control
+    ("C", Modifier::Control),
+    ("CTRL", Modifier::Control),
+    ("CONTROL", Modifier::Control),
+    // Alt
+    ("M", Modifier::Alt),
+    ("ALT", Modifier::Alt),
+    // Windows
+    ("SUPER", Modifier::Windows),
+    ("WIN", Modifier::Windows),
+    ("WINDOWS", Modifier::Windows),
+];
@@ -1,5 +1,7 @@
 use crate::config::application::deserialize_string_or_vec;
 use crate::config::application::Application;
+use crate::config::condition::Condition;
+use crate::config::device::Device;
 use crate::config::key_press::KeyPress;
 use crate::config::keymap_action::{Actions, KeymapAction};
 use evdev::Key;
@@ -17,8 +19,12 @@ pub struct Keymap {
     #[serde(deserialize_with = "deserialize_remap")]
     pub remap: HashMap<KeyPress, Vec<KeymapAction>>,
     pub application: Option<Application>,
+    pub window: Option<Application>,
+    pub device: Option<Device>,
+    pub when: Option<Condition>,
     #[serde(default, deserialize_with = "deserialize_string_or_vec")]
     pub mode: Option<Vec<String>>,
+    pub layer: Option<String>,
     #[serde(default)]
     pub exact_match: bool,
 }
@@ -40,7 +46,11 @@ pub struct KeymapEntry {
     pub actions: Vec<KeymapAction>,
     pub modifiers: Vec<Modifier>,
     pub application: Option<Application>,
+    pub window: Option<Application>,
+    pub device: Option<Device>,
+    pub when: Option<Condition>,
     pub mode: Option<Vec<String>>,
+    pub layer: Option<String>,
     pub exact_match: bool,
 }
 
@@ -62,7 +72,11 @@ pub fn build_keymap_table(keymaps: &Vec<Keymap>) -> HashMap<Key, Vec<KeymapEntry
                 actions: actions.to_vec(),
                 modifiers: key_press.modifiers.clone(),
                 application: keymap.application.clone(),
+                window: keymap.window.clone(),
+                device: keymap.device.clone(),
+                when: keymap.when.clone(),
                 mode: keymap.mode.clone(),
+                layer: keymap.layer.clone(),
                 exact_match: keymap.exact_match,
             });
             table.insert(key_press.key, entries);
@@ -2,6 +2,7 @@ use crate::config::key_press::KeyPress;
 use std::collections::HashMap;
 
 use crate::config::remap::Remap;
+use crate::config::repeat::{Repeat, RepeatActions, MAX_REPEAT_TIMES};
 use evdev::Key;
 use serde::de;
 use serde::{Deserialize, Deserializer};
@@ -29,12 +30,34 @@ pub enum KeymapAction {
     WithMark(KeyPress),
     #[serde(deserialize_with = "deserialize_escape_next_key")]
     EscapeNextKey(bool),
+    #[serde(deserialize_with = "deserialize_type")]
+    Type(String),
+    #[serde(deserialize_with = "deserialize_layer_hold")]
+    LayerHold(String),
+    #[serde(deserialize_with = "deserialize_layer_toggle")]
+    LayerToggle(String),
+    #[serde(deserialize_with = "deserialize_layer_oneshot")]
+    LayerOneshot(String),
+    #[serde(deserialize_with = "deserialize_layer_clear")]
+    LayerClear(bool),
+    #[serde(deserialize_with = "deserialize_sleep")]
+    Sleep(Duration),
+    #[serde(deserialize_with = "deserialize_repeat")]
+    Repeat(Repeat),
 
     // Internals
     #[serde(skip)]
     SetExtraModifiers(Vec<Key>),
 }
 
+// Listed in error messages since serde can't tell which untagged variant was meant
+pub const KEYMAP_ACTION_VARIANTS: &str = "a key press like `C-a`, `{ remap: ... }`, `{ launch: [...] }`, \
+    `{ set_mode: ... }`, `{ set_mark: ... }`, `{ with_mark: ... }`, `{ escape_next_key: ... }`, `{ type: ... }`, \
+    `{ layer_hold: ... }`, `{ layer_toggle: ... }`, `{ layer_oneshot: ... }`, `{ layer_clear: ... }`, `{ sleep: ... }`, or `{ repeat: ... }`";
+
+const REPEAT_ACTION_VARIANTS: &str =
+    "repeat: actions should be key presses, `{ sleep: ... }`, `{ type: ... }`, or `{ repeat: ... }`";
+
 fn deserialize_remap<'de, D>(deserializer: D) -> Result<Remap, D::Error>
 where
     D: Deserializer<'de>,
@@ -58,65 +81,115 @@ fn deserialize_launch<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let mut action = HashMap::<String, Vec<String>>::deserialize(deserializer)?;
-    if let Some(launch) = action.remove("launch") {
-        if action.is_empty() {
-            return Ok(launch);
-        }
-    }
-    Err(de::Error::custom("not a map with a single \"launch\" key"))
+    deserialize_single_key(deserializer, "launch")
 }
 
 fn deserialize_set_mode<'de, D>(deserializer: D) -> Result<String, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let mut action = HashMap::<String, String>::deserialize(deserializer)?;
-    if let Some(set) = action.remove("set_mode") {
-        if action.is_empty() {
-            return Ok(set);
-        }
-    }
-    Err(de::Error::custom("not a map with a single \"set_mode\" key"))
+    deserialize_single_key(deserializer, "set_mode")
 }
 
 fn deserialize_set_mark<'de, D>(deserializer: D) -> Result<bool, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let mut action = HashMap::<String, bool>::deserialize(deserializer)?;
-    if let Some(set) = action.remove("set_mark") {
-        if action.is_empty() {
-            return Ok(set);
-        }
-    }
-    Err(de::Error::custom("not a map with a single \"set_mark\" key"))
+    deserialize_single_key(deserializer, "set_mark")
 }
 
 fn deserialize_with_mark<'de, D>(deserializer: D) -> Result<KeyPress, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let mut action = HashMap::<String, KeyPress>::deserialize(deserializer)?;
-    if let Some(key_press) = action.remove("with_mark") {
-        if action.is_empty() {
-            return Ok(key_press);
+    deserialize_single_key(deserializer, "with_mark")
+}
+
+fn deserialize_escape_next_key<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_single_key(deserializer, "escape_next_key")
+}
+
+fn deserialize_type<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_single_key(deserializer, "type")
+}
+
+fn deserialize_layer_hold<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_single_key(deserializer, "layer_hold")
+}
+
+fn deserialize_layer_toggle<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_single_key(deserializer, "layer_toggle")
+}
+
+fn deserialize_layer_oneshot<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_single_key(deserializer, "layer_oneshot")
+}
+
+fn deserialize_layer_clear<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_single_key(deserializer, "layer_clear")
+}
+
+fn deserialize_sleep<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let millis: u64 = deserialize_single_key(deserializer, "sleep")?;
+    Ok(Duration::from_millis(millis))
+}
+
+fn deserialize_repeat<'de, D>(deserializer: D) -> Result<Repeat, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let repeat: RepeatActions = deserialize_single_key(deserializer, "repeat")?;
+    let repeat = Repeat {
+        times: repeat.times,
+        actions: repeat.actions.into_vec(),
+    };
+    // Other actions would change the state on every iteration, e.g. push `remap` multiple times
+    for action in &repeat.actions {
+        match action {
+            KeymapAction::KeyPress(_) | KeymapAction::Sleep(_) | KeymapAction::Type(_) | KeymapAction::Repeat(_) => {}
+            _ => return Err(de::Error::custom(REPEAT_ACTION_VARIANTS)),
         }
     }
-    Err(de::Error::custom("not a map with a single \"with_mark\" key"))
+    if repeat.expanded_len() > MAX_REPEAT_TIMES {
+        return Err(de::Error::custom(format!("repeat: more than {} actions are run", MAX_REPEAT_TIMES)));
+    }
+    Ok(repeat)
 }
 
-fn deserialize_escape_next_key<'de, D>(deserializer: D) -> Result<bool, D::Error>
+// Deserialize a map with a single key like `{ layer_hold: ... }`
+fn deserialize_single_key<'de, D, T>(deserializer: D, key: &str) -> Result<T, D::Error>
 where
     D: Deserializer<'de>,
+    T: Deserialize<'de>,
 {
-    let mut action = HashMap::<String, bool>::deserialize(deserializer)?;
-    if let Some(set) = action.remove("escape_next_key") {
+    let mut action = HashMap::<String, T>::deserialize(deserializer)?;
+    if let Some(value) = action.remove(key) {
         if action.is_empty() {
-            return Ok(set);
+            return Ok(value);
         }
     }
-    Err(de::Error::custom("not a map with a single \"escape_next_key\" key"))
+    Err(de::Error::custom(format!("not a map with a single \"{}\" key", key)))
 }
 
 // Used only for deserializing Vec<Action>
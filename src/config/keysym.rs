@@ -0,0 +1,181 @@
+// Names of X keysyms used in Compose files and XKB symbols, like `<minus>` or `[ eacute, 2 ]`
+pub fn keysym_to_char(name: &str) -> Option<char> {
+    if let Some((_, c)) = KEYSYM_CHARS.iter().find(|(keysym, _)| *keysym == name) {
+        return Some(*c);
+    }
+    // Latin-1 keysyms have the same values as their code points
+    if let Some(index) = LATIN1_KEYSYMS.iter().position(|keysym| *keysym == name) {
+        return char::from_u32(0xa0 + index as u32);
+    }
+    // Single letters and digits are named as they are
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if c.is_ascii_alphanumeric() {
+            return Some(c);
+        }
+    }
+    // Any Unicode character as U+hex
+    if let Some(hex) = name.strip_prefix('U') {
+        if hex.len() >= 4 {
+            return u32::from_str_radix(hex, 16).ok().and_then(char::from_u32);
+        }
+    }
+    // Keysym values. Unicode characters are offset by 0x1000000.
+    if let Some(hex) = name.strip_prefix("0x") {
+        return match u32::from_str_radix(hex, 16).ok()? {
+            value @ 0x20..=0x7e | value @ 0xa0..=0xff => char::from_u32(value),
+            value @ 0x1000100..=0x110ffff => char::from_u32(value - 0x1000000),
+            _ => None,
+        };
+    }
+    None
+}
+
+static KEYSYM_CHARS: &[(&str, char)] = &[
+    ("space", ' '),
+    ("exclam", '!'),
+    ("quotedbl", '"'),
+    ("numbersign", '#'),
+    ("dollar", '$'),
+    ("percent", '%'),
+    ("ampersand", '&'),
+    ("apostrophe", '\''),
+    ("parenleft", '('),
+    ("parenright", ')'),
+    ("asterisk", '*'),
+    ("plus", '+'),
+    ("comma", ','),
+    ("minus", '-'),
+    ("period", '.'),
+    ("slash", '/'),
+    ("colon", ':'),
+    ("semicolon", ';'),
+    ("less", '<'),
+    ("equal", '='),
+    ("greater", '>'),
+    ("question", '?'),
+    ("at", '@'),
+    ("bracketleft", '['),
+    ("backslash", '\\'),
+    ("bracketright", ']'),
+    ("asciicircum", '^'),
+    ("underscore", '_'),
+    ("grave", '`'),
+    ("braceleft", '{'),
+    ("bar", '|'),
+    ("braceright", '}'),
+    ("asciitilde", '~'),
+    // Aliases of Latin-1 keysyms
+    ("guillemotleft", '«'),
+    ("guillemotright", '»'),
+    ("masculine", 'º'),
+    ("Ooblique", 'Ø'),
+    ("ooblique", 'ø'),
+    ("Eth", 'Ð'),
+    ("Thorn", 'Þ'),
+    // Common ones outside Latin-1
+    ("EuroSign", '€'),
+    ("OE", 'Œ'),
+    ("oe", 'œ'),
+    ("Ydiaeresis", 'Ÿ'),
+];
+
+// Keysyms from 0xa0 to 0xff
+static LATIN1_KEYSYMS: &[&str] = &[
+    "nobreakspace",
+    "exclamdown",
+    "cent",
+    "sterling",
+    "currency",
+    "yen",
+    "brokenbar",
+    "section",
+    "diaeresis",
+    "copyright",
+    "ordfeminine",
+    "guillemetleft",
+    "notsign",
+    "hyphen",
+    "registered",
+    "macron",
+    "degree",
+    "plusminus",
+    "twosuperior",
+    "threesuperior",
+    "acute",
+    "mu",
+    "paragraph",
+    "periodcentered",
+    "cedilla",
+    "onesuperior",
+    "ordmasculine",
+    "guillemetright",
+    "onequarter",
+    "onehalf",
+    "threequarters",
+    "questiondown",
+    "Agrave",
+    "Aacute",
+    "Acircumflex",
+    "Atilde",
+    "Adiaeresis",
+    "Aring",
+    "AE",
+    "Ccedilla",
+    "Egrave",
+    "Eacute",
+    "Ecircumflex",
+    "Ediaeresis",
+    "Igrave",
+    "Iacute",
+    "Icircumflex",
+    "Idiaeresis",
+    "ETH",
+    "Ntilde",
+    "Ograve",
+    "Oacute",
+    "Ocircumflex",
+    "Otilde",
+    "Odiaeresis",
+    "multiply",
+    "Oslash",
+    "Ugrave",
+    "Uacute",
+    "Ucircumflex",
+    "Udiaeresis",
+    "Yacute",
+    "THORN",
+    "ssharp",
+    "agrave",
+    "aacute",
+    "acircumflex",
+    "atilde",
+    "adiaeresis",
+    "aring",
+    "ae",
+    "ccedilla",
+    "egrave",
+    "eacute",
+    "ecircumflex",
+    "ediaeresis",
+    "igrave",
+    "iacute",
+    "icircumflex",
+    "idiaeresis",
+    "eth",
+    "ntilde",
+    "ograve",
+    "oacute",
+    "ocircumflex",
+    "otilde",
+    "odiaeresis",
+    "division",
+    "oslash",
+    "ugrave",
+    "uacute",
+    "ucircumflex",
+    "udiaeresis",
+    "yacute",
+    "thorn",
+    "ydiaeresis",
+];
@@ -0,0 +1,497 @@
+use crate::config::check::key_press_name;
+use crate::config::key_press::{same_key_press, KeyPress, Modifier};
+use crate::config::keymap_action::KeymapAction;
+use crate::config::keysym::keysym_to_char;
+use crate::config::leader::LeaderNode;
+use crate::config::modmap_action::{ModmapAction, PressReleaseKey, TapDance, TapDanceHold};
+use crate::config::remap::Remap;
+use crate::config::Config;
+use evdev::Key;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+// Characters typed by key presses on a keyboard layout
+#[derive(Debug)]
+pub struct Layout {
+    chars: HashMap<char, KeyPress>,
+}
+
+impl Layout {
+    // The key press that types the character, if any
+    pub fn key_press(&self, c: char) -> Option<&KeyPress> {
+        self.chars.get(&c)
+    }
+
+    pub fn us() -> Layout {
+        let mut chars = HashMap::new();
+        for (key, normal, shifted) in US_KEYS {
+            chars.insert(
+                *normal,
+                KeyPress {
+                    key: *key,
+                    modifiers: vec![],
+                },
+            );
+            chars.insert(
+                *shifted,
+                KeyPress {
+                    key: *key,
+                    modifiers: vec![Modifier::Shift],
+                },
+            );
+        }
+        for (key, c) in [(Key::KEY_SPACE, ' '), (Key::KEY_ENTER, '\n'), (Key::KEY_TAB, '\t')] {
+            chars.insert(c, KeyPress { key, modifiers: vec![] });
+        }
+        Layout { chars }
+    }
+
+    // Read `xkb_symbols` of the layout from the first XKB directory that has it
+    pub fn from_xkb(roots: &[PathBuf], layout: &str, variant: Option<&str>) -> Result<Layout, String> {
+        let mut symbols: HashMap<String, Vec<String>> = HashMap::new();
+        read_symbols(roots, layout, variant, &mut symbols, 0)?;
+
+        let mut chars = HashMap::new();
+        for (key, c) in [(Key::KEY_ENTER, '\n'), (Key::KEY_TAB, '\t')] {
+            chars.insert(c, KeyPress { key, modifiers: vec![] });
+        }
+        // Prefer lower levels if multiple keys type the same character
+        for (level, modifiers) in [
+            vec![],
+            vec![Modifier::Shift],
+            vec![Modifier::Key(Key::KEY_RIGHTALT)],
+            vec![Modifier::Key(Key::KEY_RIGHTALT), Modifier::Shift],
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            for (name, key) in XKB_KEYS {
+                let c = symbols
+                    .get(*name)
+                    .and_then(|keysyms| keysyms.get(level))
+                    .and_then(|keysym| keysym_to_char(keysym));
+                if let Some(c) = c {
+                    chars.entry(c).or_insert_with(|| KeyPress {
+                        key: *key,
+                        modifiers: modifiers.clone(),
+                    });
+                }
+            }
+        }
+        Ok(Layout { chars })
+    }
+
+    // The key typing the same character without modifiers as the key does on the US layout
+    pub fn translate_key(&self, key: Key) -> Key {
+        US_KEYS
+            .iter()
+            .find(|(us_key, _, _)| *us_key == key)
+            .and_then(|(_, c, _)| self.chars.get(c))
+            .filter(|key_press| key_press.modifiers.is_empty())
+            .map_or(key, |key_press| key_press.key)
+    }
+
+    // Resolve key names in keymaps by character, e.g. `C-z` is the key typing `z` on this layout.
+    // Fails if two keys of a remap resolve to the same key, since one of them would be lost.
+    pub fn translate_config(&self, config: &mut Config) -> Result<(), String> {
+        for keymap in &mut config.keymap {
+            keymap.remap = self.translate_remap(std::mem::take(&mut keymap.remap))?;
+        }
+        for modmap in &mut config.modmap {
+            for action in modmap.remap.values_mut() {
+                match action {
+                    ModmapAction::PressReleaseKey(PressReleaseKey { press, release }) => {
+                        self.translate_actions(press)?;
+                        self.translate_actions(release)?;
+                    }
+                    ModmapAction::TapDance(TapDance { taps, hold, .. }) => {
+                        for tap_actions in taps {
+                            self.translate_actions(tap_actions)?;
+                        }
+                        if let Some(TapDanceHold::Actions(hold_actions)) = hold {
+                            self.translate_actions(hold_actions)?;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        for combo in &mut config.combo {
+            let original = std::mem::take(&mut combo.keys);
+            for key in &original {
+                let translated = self.translate_key(*key);
+                if let Some(index) = combo.keys.iter().position(|other| *other == translated) {
+                    return Err(collision_error(
+                        &format!("{:?}", original[index]),
+                        &format!("{:?}", key),
+                        &format!("{:?}", translated),
+                    ));
+                }
+                combo.keys.push(translated);
+            }
+            self.translate_actions(&mut combo.actions)?;
+        }
+        if let Some(leader) = &mut config.leader {
+            self.translate_leader(&mut leader.sequences)?;
+        }
+        Ok(())
+    }
+
+    fn translate_remap(
+        &self,
+        remap: HashMap<KeyPress, Vec<KeymapAction>>,
+    ) -> Result<HashMap<KeyPress, Vec<KeymapAction>>, String> {
+        let mut translated: HashMap<KeyPress, (KeyPress, Vec<KeymapAction>)> = HashMap::new();
+        for (key_press, mut actions) in remap {
+            self.translate_actions(&mut actions)?;
+            let translated_key_press = KeyPress {
+                key: self.translate_key(key_press.key),
+                modifiers: key_press.modifiers.clone(),
+            };
+            if let Some((other, _)) = translated.get(&translated_key_press) {
+                return Err(collision_error(
+                    &key_press_name(other),
+                    &key_press_name(&key_press),
+                    &key_press_name(&translated_key_press),
+                ));
+            }
+            translated.insert(translated_key_press, (key_press, actions));
+        }
+        Ok(translated
+            .into_iter()
+            .map(|(key_press, (_, actions))| (key_press, actions))
+            .collect())
+    }
+
+    fn translate_actions(&self, actions: &mut [KeymapAction]) -> Result<(), String> {
+        for action in actions {
+            match action {
+                KeymapAction::KeyPress(key_press) | KeymapAction::WithMark(key_press) => {
+                    key_press.key = self.translate_key(key_press.key);
+                }
+                KeymapAction::Remap(Remap { remap, timeout_key, .. }) => {
+                    *remap = self.translate_remap(std::mem::take(remap))?;
+                    *timeout_key = timeout_key.map(|key| self.translate_key(key));
+                }
+                KeymapAction::Repeat(repeat) => self.translate_actions(&mut repeat.actions)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn translate_leader(&self, node: &mut LeaderNode) -> Result<(), String> {
+        if let Some(actions) = &mut node.actions {
+            self.translate_actions(actions)?;
+        }
+        // (original, translated) key presses of the children translated so far
+        let mut translated: Vec<(KeyPress, KeyPress)> = vec![];
+        for (key_press, child) in &mut node.children {
+            let original = key_press.clone();
+            key_press.key = self.translate_key(key_press.key);
+            if let Some((other, _)) = translated.iter().find(|(_, other)| same_key_press(other, key_press)) {
+                return Err(collision_error(
+                    &key_press_name(other),
+                    &key_press_name(&original),
+                    &key_press_name(key_press),
+                ));
+            }
+            translated.push((original, key_press.clone()));
+            self.translate_leader(child)?;
+        }
+        Ok(())
+    }
+}
+
+fn collision_error(a: &str, b: &str, translated: &str) -> String {
+    format!("{} and {} both resolve to {} on the layout", a, b, translated)
+}
+
+// `layout: { xkb_layout: fr, variant: oss }`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LayoutConfig {
+    xkb_layout: String,
+    variant: Option<String>,
+}
+
+impl<'de> Deserialize<'de> for Layout {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let config = LayoutConfig::deserialize(deserializer)?;
+        Layout::from_xkb(&xkb_roots(), &config.xkb_layout, config.variant.as_deref()).map_err(de::Error::custom)
+    }
+}
+
+// Directories searched for symbols/<layout>, in the order of libxkbcommon
+#[cfg(not(test))]
+fn xkb_roots() -> Vec<PathBuf> {
+    let mut roots = vec![];
+    if let Some(config_home) = env::var_os("XDG_CONFIG_HOME") {
+        roots.push(Path::new(&config_home).join("xkb"));
+    } else if let Some(home) = env::var_os("HOME") {
+        roots.push(Path::new(&home).join(".config/xkb"));
+    }
+    if let Some(home) = env::var_os("HOME") {
+        roots.push(Path::new(&home).join(".xkb"));
+    }
+    roots.push(PathBuf::from("/etc/xkb"));
+    match env::var_os("XKB_CONFIG_ROOT") {
+        Some(root) => roots.push(PathBuf::from(root)),
+        None => roots.push(PathBuf::from("/usr/share/X11/xkb")),
+    }
+    roots
+}
+
+// Tests don't depend on the XKB files installed on the system
+#[cfg(test)]
+fn xkb_roots() -> Vec<PathBuf> {
+    vec![Path::new(env!("CARGO_MANIFEST_DIR")).join("src/config/fixtures/xkb")]
+}
+
+lazy_static! {
+    static ref SECTION: Regex = Regex::new(r#"((?:\w+\s+)*)xkb_symbols\s+"([^"]*)"\s*\{"#).unwrap();
+    static ref STATEMENT: Regex = Regex::new(r#"\binclude\s+"([^"]*)"|\bkey\s+<(\w+)>\s*\{([^}]*)\}"#).unwrap();
+    static ref GROUP: Regex = Regex::new(r"\[([^\]]*)\]").unwrap();
+}
+
+// Collect keysyms of each key in a section, following includes. Later definitions override earlier ones.
+fn read_symbols(
+    roots: &[PathBuf],
+    file: &str,
+    section: Option<&str>,
+    symbols: &mut HashMap<String, Vec<String>>,
+    depth: usize,
+) -> Result<(), String> {
+    let name = match section {
+        Some(section) => format!("{}({})", file, section),
+        None => file.to_string(),
+    };
+    if depth > 16 {
+        return Err(format!("too deeply nested XKB symbols: {}", name));
+    }
+    let content = roots
+        .iter()
+        .find_map(|root| fs::read_to_string(root.join("symbols").join(file)).ok())
+        .ok_or_else(|| format!("XKB symbols '{}' are not found", file))?;
+    let content: String = content
+        .lines()
+        .map(|line| line.split("//").next().unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    // Use the default section, or the first one, if it's not specified
+    let sections: Vec<_> = SECTION.captures_iter(&content).collect();
+    let found = match section {
+        Some(section) => sections.iter().find(|captures| &captures[2] == section),
+        None => sections
+            .iter()
+            .find(|captures| captures[1].split_whitespace().any(|flag| flag == "default"))
+            .or(sections.first()),
+    };
+    let start = found
+        .ok_or_else(|| format!("XKB symbols '{}' are not found", name))?
+        .get(0)
+        .unwrap()
+        .end();
+    let mut depth_in_section = 1;
+    let end = content[start..]
+        .find(|c| {
+            match c {
+                '{' => depth_in_section += 1,
+                '}' => depth_in_section -= 1,
+                _ => {}
+            }
+            depth_in_section == 0
+        })
+        .map_or(content.len(), |end| start + end);
+
+    for statement in STATEMENT.captures_iter(&content[start..end]) {
+        if let Some(include) = statement.get(1) {
+            for include in include.as_str().split(['+', '|']) {
+                // Symbols for other groups, like `:2`, aren't typed without switching groups
+                let include = match include.split_once(':') {
+                    Some((_, group)) if group != "1" => continue,
+                    Some((include, _)) => include,
+                    None => include,
+                };
+                match include.split_once('(') {
+                    Some((file, section)) => {
+                        read_symbols(roots, file, Some(section.trim_end_matches(')')), symbols, depth + 1)?
+                    }
+                    None => read_symbols(roots, include, None, symbols, depth + 1)?,
+                }
+            }
+        } else if let (Some(key), Some(body)) = (statement.get(2), statement.get(3)) {
+            // The first group that isn't a `[Group1]` index like in `symbols[Group1] = [ ... ]`
+            let keysyms = GROUP
+                .captures_iter(body.as_str())
+                .map(|group| group[1].trim().to_string())
+                .find(|group| !group.starts_with("Group"));
+            if let Some(keysyms) = keysyms {
+                let keysyms = keysyms.split(',').map(|keysym| keysym.trim().to_string()).collect();
+                symbols.insert(key.as_str().to_string(), keysyms);
+            }
+        }
+    }
+    Ok(())
+}
+
+// (key, character without Shift, character with Shift) on the US layout
+static US_KEYS: &[(Key, char, char)] = &[
+    (Key::KEY_GRAVE, '`', '~'),
+    (Key::KEY_1, '1', '!'),
+    (Key::KEY_2, '2', '@'),
+    (Key::KEY_3, '3', '#'),
+    (Key::KEY_4, '4', '$'),
+    (Key::KEY_5, '5', '%'),
+    (Key::KEY_6, '6', '^'),
+    (Key::KEY_7, '7', '&'),
+    (Key::KEY_8, '8', '*'),
+    (Key::KEY_9, '9', '('),
+    (Key::KEY_0, '0', ')'),
+    (Key::KEY_MINUS, '-', '_'),
+    (Key::KEY_EQUAL, '=', '+'),
+    (Key::KEY_Q, 'q', 'Q'),
+    (Key::KEY_W, 'w', 'W'),
+    (Key::KEY_E, 'e', 'E'),
+    (Key::KEY_R, 'r', 'R'),
+    (Key::KEY_T, 't', 'T'),
+    (Key::KEY_Y, 'y', 'Y'),
+    (Key::KEY_U, 'u', 'U'),
+    (Key::KEY_I, 'i', 'I'),
+    (Key::KEY_O, 'o', 'O'),
+    (Key::KEY_P, 'p', 'P'),
+    (Key::KEY_LEFTBRACE, '[', '{'),
+    (Key::KEY_RIGHTBRACE, ']', '}'),
+    (Key::KEY_BACKSLASH, '\\', '|'),
+    (Key::KEY_A, 'a', 'A'),
+    (Key::KEY_S, 's', 'S'),
+    (Key::KEY_D, 'd', 'D'),
+    (Key::KEY_F, 'f', 'F'),
+    (Key::KEY_G, 'g', 'G'),
+    (Key::KEY_H, 'h', 'H'),
+    (Key::KEY_J, 'j', 'J'),
+    (Key::KEY_K, 'k', 'K'),
+    (Key::KEY_L, 'l', 'L'),
+    (Key::KEY_SEMICOLON, ';', ':'),
+    (Key::KEY_APOSTROPHE, '\'', '"'),
+    (Key::KEY_Z, 'z', 'Z'),
+    (Key::KEY_X, 'x', 'X'),
+    (Key::KEY_C, 'c', 'C'),
+    (Key::KEY_V, 'v', 'V'),
+    (Key::KEY_B, 'b', 'B'),
+    (Key::KEY_N, 'n', 'N'),
+    (Key::KEY_M, 'm', 'M'),
+    (Key::KEY_COMMA, ',', '<'),
+    (Key::KEY_DOT, '.', '>'),
+    (Key::KEY_SLASH, '/', '?'),
+];
+
+// Names of keys in XKB symbols that type characters
+static XKB_KEYS: &[(&str, Key)] = &[
+    ("TLDE", Key::KEY_GRAVE),
+    ("AE01", Key::KEY_1),
+    ("AE02", Key::KEY_2),
+    ("AE03", Key::KEY_3),
+    ("AE04", Key::KEY_4),
+    ("AE05", Key::KEY_5),
+    ("AE06", Key::KEY_6),
+    ("AE07", Key::KEY_7),
+    ("AE08", Key::KEY_8),
+    ("AE09", Key::KEY_9),
+    ("AE10", Key::KEY_0),
+    ("AE11", Key::KEY_MINUS),
+    ("AE12", Key::KEY_EQUAL),
+    ("AD01", Key::KEY_Q),
+    ("AD02", Key::KEY_W),
+    ("AD03", Key::KEY_E),
+    ("AD04", Key::KEY_R),
+    ("AD05", Key::KEY_T),
+    ("AD06", Key::KEY_Y),
+    ("AD07", Key::KEY_U),
+    ("AD08", Key::KEY_I),
+    ("AD09", Key::KEY_O),
+    ("AD10", Key::KEY_P),
+    ("AD11", Key::KEY_LEFTBRACE),
+    ("AD12", Key::KEY_RIGHTBRACE),
+    ("BKSL", Key::KEY_BACKSLASH),
+    ("AC12", Key::KEY_BACKSLASH),
+    ("AC01", Key::KEY_A),
+    ("AC02", Key::KEY_S),
+    ("AC03", Key::KEY_D),
+    ("AC04", Key::KEY_F),
+    ("AC05", Key::KEY_G),
+    ("AC06", Key::KEY_H),
+    ("AC07", Key::KEY_J),
+    ("AC08", Key::KEY_K),
+    ("AC09", Key::KEY_L),
+    ("AC10", Key::KEY_SEMICOLON),
+    ("AC11", Key::KEY_APOSTROPHE),
+    ("LSGT", Key::KEY_102ND),
+    ("AB01", Key::KEY_Z),
+    ("AB02", Key::KEY_X),
+    ("AB03", Key::KEY_C),
+    ("AB04", Key::KEY_V),
+    ("AB05", Key::KEY_B),
+    ("AB06", Key::KEY_N),
+    ("AB07", Key::KEY_M),
+    ("AB08", Key::KEY_COMMA),
+    ("AB09", Key::KEY_DOT),
+    ("AB10", Key::KEY_SLASH),
+    ("SPCE", Key::KEY_SPACE),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_from_xkb() {
+        let layout = Layout::from_xkb(&xkb_roots(), "fr", None).unwrap();
+
+        let key_press = |key, modifiers| Some(KeyPress { key, modifiers });
+        assert_eq!(key_press(Key::KEY_W, vec![]), layout.key_press('z').cloned());
+        assert_eq!(key_press(Key::KEY_Q, vec![Modifier::Shift]), layout.key_press('A').cloned());
+        assert_eq!(key_press(Key::KEY_2, vec![]), layout.key_press('é').cloned());
+        assert_eq!(key_press(Key::KEY_1, vec![Modifier::Shift]), layout.key_press('1').cloned());
+        assert_eq!(key_press(Key::KEY_2, vec![Modifier::Key(Key::KEY_RIGHTALT)]), layout.key_press('~').cloned());
+        assert_eq!(None, layout.key_press('@'));
+
+        assert_eq!(Key::KEY_W, layout.translate_key(Key::KEY_Z));
+        assert_eq!(Key::KEY_Q, layout.translate_key(Key::KEY_A));
+        assert_eq!(Key::KEY_1, layout.translate_key(Key::KEY_1));
+        assert_eq!(Key::KEY_ESC, layout.translate_key(Key::KEY_ESC));
+    }
+
+    #[test]
+    fn test_translate_config() {
+        let layout = Layout::from_xkb(&xkb_roots(), "fr", None).unwrap();
+        let mut config: Config = serde_yaml::from_str(indoc! {"
+        combo:
+          - keys: [a, z]
+            actions: C-a
+        "})
+        .unwrap();
+        layout.translate_config(&mut config).unwrap();
+        assert_eq!(vec![Key::KEY_Q, Key::KEY_W], config.combo[0].keys);
+
+        // `a` resolves to Q, while `q` isn't on the layout and stays Q
+        let mut config: Config = serde_yaml::from_str(indoc! {"
+        keymap:
+          - remap:
+              C-a: C-x
+              C-q: C-y
+        "})
+        .unwrap();
+        let error = layout.translate_config(&mut config).unwrap_err();
+        assert!(error.contains("both resolve to Control-KEY_Q"), "{}", error);
+    }
+}
@@ -0,0 +1,106 @@
+use crate::config::key_press::{parse_key_press, same_key_press, KeyPress};
+use crate::config::keymap_action::{Actions, KeymapAction};
+use serde::{de, Deserialize, Deserializer};
+use serde_with::{serde_as, DurationMilliSeconds};
+use std::collections::HashMap;
+use std::time::Duration;
+
+// Flat sequences of key presses like `leader g s`, compiled into a trie
+#[derive(Debug)]
+pub struct Leader {
+    pub sequences: LeaderNode,
+    // How long to wait for the next key of a sequence
+    pub timeout: Duration,
+    // Replay the swallowed keys when a sequence doesn't match
+    pub replay: bool,
+}
+
+// A node of the trie. Its path from the root is a prefix of some sequences.
+#[derive(Debug, Default)]
+pub struct LeaderNode {
+    // Dispatched when a sequence ends at this node
+    pub actions: Option<Vec<KeymapAction>>,
+    pub children: Vec<(KeyPress, LeaderNode)>,
+}
+
+impl LeaderNode {
+    // Follow the indexes of children from this node
+    pub fn get(&self, path: &[usize]) -> &LeaderNode {
+        path.iter().fold(self, |node, index| &node.children[*index].1)
+    }
+
+    fn insert(&mut self, sequence: &[KeyPress], actions: Vec<KeymapAction>) -> Result<(), String> {
+        match sequence.split_first() {
+            None if self.actions.is_some() => Err("duplicate leader sequence".to_string()),
+            None => {
+                self.actions = Some(actions);
+                Ok(())
+            }
+            Some((key_press, rest)) => {
+                let index = match self
+                    .children
+                    .iter()
+                    .position(|(child, _)| same_key_press(child, key_press))
+                {
+                    Some(index) => index,
+                    None => {
+                        self.children.push((key_press.clone(), LeaderNode::default()));
+                        self.children.len() - 1
+                    }
+                };
+                self.children[index].1.insert(rest, actions)
+            }
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LeaderConfig {
+    key: Option<KeyPress>,
+    sequences: HashMap<String, Actions>,
+    #[serde_as(as = "DurationMilliSeconds")]
+    #[serde(default = "default_timeout", rename = "timeout_millis")]
+    timeout: Duration,
+    #[serde(default)]
+    replay: bool,
+}
+
+impl<'de> Deserialize<'de> for Leader {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let config = LeaderConfig::deserialize(deserializer)?;
+        let mut sequences = LeaderNode::default();
+        for (sequence, actions) in config.sequences {
+            let mut key_presses = vec![];
+            for name in sequence.split_whitespace() {
+                if name.eq_ignore_ascii_case("leader") {
+                    match &config.key {
+                        Some(key) => key_presses.push(key.clone()),
+                        None => return Err(de::Error::custom("`key` is required to use `leader` in a sequence")),
+                    }
+                } else {
+                    key_presses.push(parse_key_press(name).map_err(de::Error::custom)?);
+                }
+            }
+            if key_presses.is_empty() {
+                return Err(de::Error::custom("empty leader sequence"));
+            }
+            sequences
+                .insert(&key_presses, actions.into_vec())
+                .map_err(|e| de::Error::custom(format!("{}: {}", e, sequence)))?;
+        }
+        Ok(Leader {
+            sequences,
+            timeout: config.timeout,
+            replay: config.replay,
+        })
+    }
+}
+
+fn default_timeout() -> Duration {
+    Duration::from_millis(1000)
+}
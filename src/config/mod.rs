@@ -1,27 +1,53 @@
 pub mod application;
+pub mod check;
+pub mod combo;
+pub mod condition;
+pub mod device;
+pub mod diff;
 mod key;
 pub mod key_press;
 pub mod keymap;
 pub mod keymap_action;
+mod keysym;
+pub mod layout;
+pub mod leader;
 mod modmap;
 pub mod modmap_action;
 
 pub mod remap;
+pub mod repeat;
+pub mod schema;
 #[cfg(test)]
 mod tests;
+pub mod unicode_input;
 
 extern crate serde_yaml;
 
+use combo::Combo;
 use evdev::Key;
 use keymap::Keymap;
+use layout::Layout;
+use lazy_static::lazy_static;
+use leader::Leader;
 use modmap::Modmap;
 use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
 use serde::{Deserialize, Deserializer};
-use std::{collections::HashMap, error, fs, path::Path, time::SystemTime};
+use std::{
+    collections::HashMap,
+    error,
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
 
 use self::{
+    application::deserialize_string_or_vec,
     key::parse_key,
     keymap::{build_keymap_table, KeymapEntry},
+    keymap_action::KEYMAP_ACTION_VARIANTS,
+    modmap_action::MODMAP_ACTION_VARIANTS,
+    unicode_input::UnicodeInput,
 };
 
 #[derive(Debug, Deserialize)]
@@ -32,26 +58,137 @@ pub struct Config {
     pub modmap: Vec<Modmap>,
     #[serde(default = "Vec::new")]
     pub keymap: Vec<Keymap>,
-    #[serde(default = "default_mode")]
-    pub default_mode: String,
+    #[serde(default = "Vec::new")]
+    pub combo: Vec<Combo>,
+    #[serde(default)]
+    pub leader: Option<Leader>,
+    // Use default_mode() to read this. None if no config file sets it.
+    #[serde(default)]
+    pub default_mode: Option<String>,
     #[serde(deserialize_with = "deserialize_virtual_modifiers", default = "Vec::new")]
     pub virtual_modifiers: Vec<Key>,
+    // Use keypress_delay() to read this. None if no config file sets it.
+    #[serde(default)]
+    pub keypress_delay_ms: Option<u64>,
+    // Use layout() to read this. None if no config file sets it.
     #[serde(default)]
-    pub keypress_delay_ms: u64,
+    pub layout: Option<Layout>,
+    // Use unicode_input() to read this. None if no config file sets it.
+    #[serde(default)]
+    pub unicode_input: Option<UnicodeInput>,
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub include: Option<Vec<String>>,
 
     // Internals
     #[serde(skip)]
     pub modify_time: Option<SystemTime>,
     #[serde(skip)]
+    pub config_files: Vec<PathBuf>,
+    // `include` globs joined to the directory of the including file
+    #[serde(skip)]
+    pub include_globs: Vec<PathBuf>,
+    #[serde(skip)]
     pub keymap_table: HashMap<Key, Vec<KeymapEntry>>,
 }
 
-pub fn load_config(filename: &Path) -> Result<Config, Box<dyn error::Error>> {
-    let yaml = fs::read_to_string(&filename)?;
-    let mut config: Config = serde_yaml::from_str(&yaml)?;
+impl Config {
+    // Files and directories whose changes are reloaded by --watch=config
+    pub fn watched_paths(&self) -> Vec<PathBuf> {
+        let mut paths = self.config_files.clone();
+        paths.extend(self.include_dirs());
+        paths
+    }
+
+    // Directories where new files may match `include` globs. Globs in directory names aren't watched.
+    pub fn include_dirs(&self) -> Vec<PathBuf> {
+        self.include_globs
+            .iter()
+            .filter_map(|glob| glob.parent())
+            .filter(|dir| !dir.to_string_lossy().contains(['*', '?', '[']))
+            .map(Path::to_path_buf)
+            .collect()
+    }
+
+    // Whether a file created with the name would be loaded by an `include` glob
+    pub fn matches_include_glob(&self, name: &OsStr) -> bool {
+        self.include_globs.iter().any(|glob| {
+            let pattern = glob.file_name().map(|name| glob::Pattern::new(&name.to_string_lossy()));
+            matches!(pattern, Some(Ok(pattern)) if pattern.matches(&name.to_string_lossy()))
+        })
+    }
+
+    pub fn default_mode(&self) -> &str {
+        self.default_mode.as_deref().unwrap_or("default")
+    }
+
+    // Modes that make a difference when they're set
+    pub fn modes(&self) -> Vec<&str> {
+        let mut modes = vec![self.default_mode()];
+        for keymap in &self.keymap {
+            modes.extend(keymap.mode.iter().flatten().map(String::as_str));
+        }
+        modes
+    }
+
+    pub fn keypress_delay(&self) -> Duration {
+        Duration::from_millis(self.keypress_delay_ms.unwrap_or_default())
+    }
+
+    pub fn layout(&self) -> &Layout {
+        self.layout.as_ref().unwrap_or(&US_LAYOUT)
+    }
+
+    pub fn unicode_input(&self) -> &UnicodeInput {
+        self.unicode_input.as_ref().unwrap_or(&UnicodeInput::CtrlShiftU)
+    }
+}
+
+lazy_static! {
+    static ref US_LAYOUT: Layout = Layout::us();
+}
+
+// Load config files given from the command line. modmap and keymap are concatenated in order,
+// and scalar settings and leader are taken from the last file that sets them.
+pub fn load_configs(filenames: &[PathBuf]) -> Result<Config, Box<dyn error::Error>> {
+    let mut configs = vec![];
+    for filename in filenames {
+        configs.push(load_config_file(filename, &mut vec![])?);
+    }
+    merge_configs(configs)
+}
+
+// Merge configs loaded from files given from the command line, and prepare the result for remapping
+pub fn merge_configs(configs: Vec<Config>) -> Result<Config, Box<dyn error::Error>> {
+    let mut config: Option<Config> = None;
+    for c in configs {
+        config = match config {
+            None => Some(c),
+            Some(mut config) => {
+                config.modmap.extend(c.modmap);
+                config.keymap.extend(c.keymap);
+                config.combo.extend(c.combo);
+                config.leader = c.leader.or(config.leader);
+                config.virtual_modifiers.extend(c.virtual_modifiers);
+                config.default_mode = c.default_mode.or(config.default_mode);
+                config.keypress_delay_ms = c.keypress_delay_ms.or(config.keypress_delay_ms);
+                config.layout = c.layout.or(config.layout);
+                config.unicode_input = c.unicode_input.or(config.unicode_input);
+                config.config_files.extend(c.config_files);
+                config.include_globs.extend(c.include_globs);
+                Some(config)
+            }
+        };
+    }
+    let mut config = config.ok_or("no config file is given")?;
 
     // Timestamp for --watch=config
-    config.modify_time = filename.metadata()?.modified().ok();
+    config.modify_time = modify_time(&config.watched_paths());
+
+    // Resolve keys by character on the layout before building the table from them
+    if let Some(layout) = config.layout.take() {
+        layout.translate_config(&mut config)?;
+        config.layout = Some(layout);
+    }
 
     // Convert keymap for efficient keymap lookup
     config.keymap_table = build_keymap_table(&config.keymap);
@@ -59,22 +196,187 @@ pub fn load_config(filename: &Path) -> Result<Config, Box<dyn error::Error>> {
     Ok(config)
 }
 
-pub fn config_watcher(watch: bool, file: &Path) -> anyhow::Result<Option<Inotify>> {
+// Load a config file and the files it includes, recursively. Entries of the including file
+// come first so that they take precedence over the included ones, which are merged in order.
+// Errors are prefixed with the path of the file that caused them.
+pub fn load_config_file(filename: &Path, ancestors: &mut Vec<PathBuf>) -> Result<Config, Box<dyn error::Error>> {
+    let file_error = |e: &dyn error::Error| format!("{}: {}", filename.display(), e);
+    let canonical_path = filename.canonicalize().map_err(|e| file_error(&e))?;
+    if ancestors.contains(&canonical_path) {
+        return Err(format!("{}: included recursively", filename.display()).into());
+    }
+
+    let content = fs::read_to_string(&filename).map_err(|e| file_error(&e))?;
+    let mut config = parse_config(&content, ConfigFormat::from_path(filename)).map_err(|e| e.with_file(filename))?;
+    config.config_files.push(filename.to_path_buf());
+
+    ancestors.push(canonical_path);
+    let includes = resolve_includes(filename, config.include.as_deref().unwrap_or_default(), &mut config.include_globs);
+    for include in includes.map_err(|e| file_error(e.as_ref()))? {
+        let included = load_config_file(&include, ancestors)?;
+        config.modmap.extend(included.modmap);
+        config.keymap.extend(included.keymap);
+        config.combo.extend(included.combo);
+        config.leader = config.leader.or(included.leader);
+        config.virtual_modifiers.extend(included.virtual_modifiers);
+        config.default_mode = config.default_mode.or(included.default_mode);
+        config.keypress_delay_ms = config.keypress_delay_ms.or(included.keypress_delay_ms);
+        config.layout = config.layout.or(included.layout);
+        config.unicode_input = config.unicode_input.or(included.unicode_input);
+        config.config_files.extend(included.config_files);
+        config.include_globs.extend(included.include_globs);
+    }
+    ancestors.pop();
+
+    Ok(config)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Yaml,
+    Json,
+    Toml,
+}
+
+impl ConfigFormat {
+    // Detected from the file extension. YAML is the default for compatibility.
+    pub fn from_path(path: &Path) -> ConfigFormat {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => ConfigFormat::Json,
+            Some("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Yaml,
+        }
+    }
+}
+
+// Error of parse_config with a (line, column) location if the format reports it
+#[derive(Debug)]
+pub struct ParseError {
+    pub message: String,
+    pub location: Option<(usize, usize)>,
+}
+
+pub fn parse_config(content: &str, format: ConfigFormat) -> Result<Config, ParseError> {
+    let error = match format {
+        ConfigFormat::Yaml => match serde_yaml::from_str(content) {
+            Ok(config) => return Ok(config),
+            Err(e) => ParseError {
+                location: e.location().map(|location| (location.line(), location.column())),
+                message: e.to_string(),
+            },
+        },
+        ConfigFormat::Json => match serde_json::from_str(content) {
+            Ok(config) => return Ok(config),
+            Err(e) => ParseError {
+                location: Some((e.line(), e.column())).filter(|(line, _)| *line > 0),
+                message: e.to_string(),
+            },
+        },
+        ConfigFormat::Toml => match toml::from_str(content) {
+            Ok(config) => return Ok(config),
+            Err(e) => ParseError {
+                location: e.span().map(|span| line_column(content, span.start)),
+                message: e.message().to_string(),
+            },
+        },
+    };
+    Err(error.with_expected_variants())
+}
+
+impl ParseError {
+    // serde can't tell why each variant of an untagged enum failed, so list what's expected.
+    fn with_expected_variants(mut self) -> ParseError {
+        if self.location.is_some() {
+            // serde_yaml and serde_json append the location to the message
+            if let Some((message, _)) = self.message.rsplit_once(" at line ") {
+                self.message = message.to_string();
+            }
+        }
+        for (enum_name, variants) in [
+            ("Actions", format!("{}, or a list of them", KEYMAP_ACTION_VARIANTS)),
+            ("KeymapAction", KEYMAP_ACTION_VARIANTS.to_string()),
+            ("ModmapAction", MODMAP_ACTION_VARIANTS.to_string()),
+        ] {
+            if self.message.ends_with(&format!("untagged enum {}", enum_name)) {
+                self.message = format!("{}. Expected {}", self.message, variants);
+            }
+        }
+        self
+    }
+
+    // Format an error as `file:line:column: message`
+    fn with_file(&self, filename: &Path) -> String {
+        match self.location {
+            Some((line, column)) => format!("{}:{}:{}: {}", filename.display(), line, column, self.message),
+            None => format!("{}: {}", filename.display(), self.message),
+        }
+    }
+}
+
+// 1-based line and column of a byte offset
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line_start = before.rfind('\n').map_or(0, |pos| pos + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+// Expand `include` paths and globs relative to the directory of the including file.
+// The globs are collected to watch new files matching them.
+fn resolve_includes(
+    filename: &Path,
+    includes: &[String],
+    globs: &mut Vec<PathBuf>,
+) -> Result<Vec<PathBuf>, Box<dyn error::Error>> {
+    let dir = filename.parent().unwrap_or_else(|| Path::new(""));
+    let mut paths = vec![];
+    for include in includes {
+        let pattern = dir.join(include);
+        if include.contains(['*', '?', '[']) {
+            let mut matches = glob::glob(&pattern.to_string_lossy())?.collect::<Result<Vec<_>, _>>()?;
+            matches.sort();
+            paths.extend(matches);
+            globs.push(pattern);
+        } else {
+            paths.push(pattern);
+        }
+    }
+    Ok(paths)
+}
+
+// The latest modification time of all config files and include directories. None if any of them can't be read.
+pub fn modify_time(files: &[PathBuf]) -> Option<SystemTime> {
+    let mut latest = None;
+    for file in files {
+        let modified = file.metadata().and_then(|m| m.modified()).ok()?;
+        latest = latest.max(Some(modified));
+    }
+    latest
+}
+
+pub fn config_watcher(watch: bool, config: &Config) -> anyhow::Result<Option<Inotify>> {
     if watch {
         let inotify = Inotify::init(InitFlags::IN_NONBLOCK)?;
-        inotify.add_watch(
-            file.parent().expect("config file has a parent directory"),
-            AddWatchFlags::IN_CREATE | AddWatchFlags::IN_MOVED_TO,
-        )?;
-        inotify.add_watch(file, AddWatchFlags::IN_MODIFY)?;
+        add_config_watches(&inotify, config)?;
         Ok(Some(inotify))
     } else {
         Ok(None)
     }
 }
 
-fn default_mode() -> String {
-    "default".to_string()
+// Also called on reload since `include` may start loading new files.
+pub fn add_config_watches(inotify: &Inotify, config: &Config) -> anyhow::Result<()> {
+    for file in &config.config_files {
+        inotify.add_watch(
+            file.parent().expect("config file has a parent directory"),
+            AddWatchFlags::IN_CREATE | AddWatchFlags::IN_MOVED_TO,
+        )?;
+        inotify.add_watch(file, AddWatchFlags::IN_MODIFY)?;
+    }
+    // Files created later may match `include` globs
+    for dir in config.include_dirs() {
+        inotify.add_watch(&dir, AddWatchFlags::IN_CREATE | AddWatchFlags::IN_MOVED_TO)?;
+    }
+    Ok(())
 }
 
 fn deserialize_virtual_modifiers<'de, D>(deserializer: D) -> Result<Vec<Key>, D::Error>
@@ -1,4 +1,6 @@
 use crate::config::application::Application;
+use crate::config::condition::Condition;
+use crate::config::device::Device;
 use crate::config::key::deserialize_key;
 use crate::config::modmap_action::ModmapAction;
 use evdev::Key;
@@ -13,6 +15,9 @@ pub struct Modmap {
     #[serde(deserialize_with = "deserialize_remap")]
     pub remap: HashMap<Key, ModmapAction>,
     pub application: Option<Application>,
+    pub window: Option<Application>,
+    pub device: Option<Device>,
+    pub when: Option<Condition>,
 }
 
 fn deserialize_remap<'de, D>(deserializer: D) -> Result<HashMap<Key, ModmapAction>, D::Error>
@@ -1,7 +1,8 @@
 use crate::config::key::deserialize_key;
 use evdev::Key;
-use serde::{Deserialize, Deserializer};
+use serde::{de, Deserialize, Deserializer};
 use serde_with::{serde_as, DurationMilliSeconds};
+use std::collections::HashMap;
 use std::time::Duration;
 
 use super::keymap_action::{Actions, KeymapAction};
@@ -14,8 +15,16 @@ pub enum ModmapAction {
     Key(Key),
     MultiPurposeKey(MultiPurposeKey),
     PressReleaseKey(PressReleaseKey),
+    #[serde(deserialize_with = "deserialize_tap_dance")]
+    TapDance(TapDance),
+    OneShot(OneShot),
 }
 
+// Listed in error messages since serde can't tell which untagged variant was meant
+pub const MODMAP_ACTION_VARIANTS: &str =
+    "a key like `Ctrl_L`, `{ held: ..., alone: ... }`, `{ press: ..., release: ... }`, `{ tap_dance: ... }`, \
+    or `{ one_shot: ... }`";
+
 #[serde_as]
 #[derive(Clone, Debug, Deserialize)]
 pub struct MultiPurposeKey {
@@ -36,6 +45,63 @@ pub struct PressReleaseKey {
     pub release: Vec<KeymapAction>,
 }
 
+// Dispatch taps[n - 1] when the key is tapped n times within timeout_millis of each other.
+// If the key is still held when the timeout is reached, `hold` is used instead.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TapDance {
+    #[serde(deserialize_with = "deserialize_taps")]
+    pub taps: Vec<Vec<KeymapAction>>,
+    #[serde(default)]
+    pub hold: Option<TapDanceHold>,
+    #[serde_as(as = "DurationMilliSeconds")]
+    #[serde(default = "default_tap_timeout", rename = "timeout_millis")]
+    pub timeout: Duration,
+}
+
+// `tap_dance.hold`
+#[derive(Clone, Debug)]
+pub enum TapDanceHold {
+    // A key like `Ctrl_L`, held until the key is released
+    Key(Key),
+    // Actions dispatched once, like `taps`
+    Actions(Vec<KeymapAction>),
+}
+
+impl<'de> Deserialize<'de> for TapDanceHold {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // A key name is taken as a key to hold before trying it as a key press action
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Hold {
+            #[serde(deserialize_with = "deserialize_key")]
+            Key(Key),
+            Actions(Actions),
+        }
+        Ok(match Hold::deserialize(deserializer)? {
+            Hold::Key(key) => TapDanceHold::Key(key),
+            Hold::Actions(actions) => TapDanceHold::Actions(actions.into_vec()),
+        })
+    }
+}
+
+// Hold `one_shot` while the key is held. If the key is tapped alone, keep holding it
+// until the next non-modifier key is pressed or timeout_millis passes.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OneShot {
+    #[serde(deserialize_with = "deserialize_key")]
+    pub one_shot: Key,
+    #[serde_as(as = "Option<DurationMilliSeconds>")]
+    #[serde(default, rename = "timeout_millis")]
+    pub timeout: Option<Duration>,
+}
+
 pub fn deserialize_actions<'de, D>(deserializer: D) -> Result<Vec<KeymapAction>, D::Error>
 where
     D: Deserializer<'de>,
@@ -47,3 +113,34 @@ where
 fn default_alone_timeout() -> Duration {
     Duration::from_millis(1000)
 }
+
+fn deserialize_tap_dance<'de, D>(deserializer: D) -> Result<TapDance, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut action = HashMap::<String, TapDance>::deserialize(deserializer)?;
+    if let Some(tap_dance) = action.remove("tap_dance") {
+        if action.is_empty() {
+            return Ok(tap_dance);
+        }
+    }
+    Err(de::Error::custom("not a map with a single \"tap_dance\" key"))
+}
+
+fn deserialize_taps<'de, D>(deserializer: D) -> Result<Vec<Vec<KeymapAction>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let taps: Vec<Vec<KeymapAction>> = Vec::<Actions>::deserialize(deserializer)?
+        .into_iter()
+        .map(Actions::into_vec)
+        .collect();
+    if taps.is_empty() {
+        return Err(de::Error::custom("taps must have at least one action"));
+    }
+    Ok(taps)
+}
+
+fn default_tap_timeout() -> Duration {
+    Duration::from_millis(200)
+}
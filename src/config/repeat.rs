@@ -0,0 +1,37 @@
+use serde::Deserialize;
+
+use crate::config::keymap_action::{Actions, KeymapAction};
+
+// Actions are expanded at once on a key press, so keep it from blocking the event loop.
+// This also limits the actions run by nested repeats in total.
+pub const MAX_REPEAT_TIMES: usize = 1000;
+
+// `{ repeat: { times: 3, actions: [...] } }`
+#[derive(Clone, Debug)]
+pub struct Repeat {
+    pub times: usize,
+    pub actions: Vec<KeymapAction>,
+}
+
+impl Repeat {
+    // The number of actions run, counting nested repeats
+    pub fn expanded_len(&self) -> usize {
+        let actions: usize = self
+            .actions
+            .iter()
+            .map(|action| match action {
+                KeymapAction::Repeat(repeat) => repeat.expanded_len(),
+                _ => 1,
+            })
+            .fold(0, usize::saturating_add);
+        self.times.saturating_mul(actions)
+    }
+}
+
+// Used only for deserialization
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RepeatActions {
+    pub times: usize,
+    pub actions: Actions,
+}
@@ -0,0 +1,450 @@
+use crate::config::key::key_names;
+use crate::config::key_press::MODIFIER_ALIASES;
+use crate::config::repeat::MAX_REPEAT_TIMES;
+use serde_json::{json, Value};
+
+// JSON Schema of the config file for `--print-schema`. This is written by hand since
+// the config is deserialized with custom deserializers that a derived schema wouldn't reflect.
+pub fn config_schema() -> Value {
+    let keys = key_names();
+    let key_pattern = key_name_pattern(&keys);
+    let modifier_pattern = format!(
+        "{}|{}",
+        MODIFIER_ALIASES
+            .iter()
+            .map(|(alias, _)| case_insensitive(alias))
+            .collect::<Vec<_>>()
+            .join("|"),
+        key_pattern
+    );
+
+    // Actions allowed in `repeat` as well
+    let type_action = single_key_object("type", json!({ "type": "string" }));
+    let sleep_action =
+        single_key_object("sleep", json!({ "type": "integer", "minimum": 0, "description": "Milliseconds" }));
+    let repeat_action = single_key_object(
+        "repeat",
+        json!({
+            "type": "object",
+            "additionalProperties": false,
+            "required": ["times", "actions"],
+            "properties": {
+                "times": { "type": "integer", "minimum": 0, "maximum": MAX_REPEAT_TIMES },
+                "actions": {
+                    "anyOf": [
+                        { "$ref": "#/definitions/repeat_action" },
+                        { "type": "array", "items": { "$ref": "#/definitions/repeat_action" } },
+                    ],
+                },
+            },
+        }),
+    );
+
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "xremap config",
+        "type": "object",
+        "additionalProperties": false,
+        "properties": {
+            "modmap": { "type": "array", "items": { "$ref": "#/definitions/modmap" } },
+            "keymap": { "type": "array", "items": { "$ref": "#/definitions/keymap" } },
+            "combo": { "type": "array", "items": { "$ref": "#/definitions/combo" } },
+            "leader": { "$ref": "#/definitions/leader" },
+            "default_mode": { "type": "string", "default": "default" },
+            "virtual_modifiers": { "type": "array", "items": { "$ref": "#/definitions/key" } },
+            "keypress_delay_ms": { "type": "integer", "minimum": 0, "default": 0 },
+            "layout": {
+                "description": "The XKB layout to resolve keys and `type` by character. US QWERTY by default.",
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["xkb_layout"],
+                "properties": {
+                    "xkb_layout": { "type": "string" },
+                    "variant": { "type": "string" },
+                },
+            },
+            "unicode_input": {
+                "description": "How `type` enters characters that the layout can't type",
+                "anyOf": [
+                    { "const": "ctrl_shift_u" },
+                    {
+                        "type": "object",
+                        "additionalProperties": false,
+                        "required": ["compose"],
+                        "properties": {
+                            "compose": { "$ref": "#/definitions/key" },
+                            "compose_file": { "type": "string" },
+                        },
+                    },
+                ],
+                "default": "ctrl_shift_u",
+            },
+            "include": {
+                "description": "Paths or globs of config files to include, relative to this file",
+                "$ref": "#/definitions/string_or_array",
+            },
+        },
+        "definitions": {
+            "key": {
+                "description": "A key name. Case-insensitive, and KEY_ may be omitted.",
+                "type": "string",
+                "anyOf": [
+                    { "enum": keys },
+                    { "pattern": format!("^(?:{})$", key_pattern) },
+                ],
+            },
+            "key_press": {
+                "description": "A key with optional modifiers like C-M-a",
+                "type": "string",
+                "pattern": format!("^(?:(?:{})-)*(?:{})$", modifier_pattern, key_pattern),
+            },
+            "string_or_array": {
+                "anyOf": [
+                    { "type": "string" },
+                    { "type": "array", "items": { "type": "string" } },
+                ],
+            },
+            "application": {
+                "description": "Application names, or regexes surrounded by /",
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "only": { "$ref": "#/definitions/string_or_array" },
+                    "not": { "$ref": "#/definitions/string_or_array" },
+                },
+            },
+            "window": {
+                "description": "Window titles, or regexes surrounded by /",
+                "$ref": "#/definitions/application",
+            },
+            "device": {
+                "description": "Device names or paths, matched like --device",
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "only": { "$ref": "#/definitions/string_or_array" },
+                    "not": { "$ref": "#/definitions/string_or_array" },
+                },
+            },
+            "condition": {
+                "description": "A map with exactly one condition",
+                "type": "object",
+                "additionalProperties": false,
+                "minProperties": 1,
+                "maxProperties": 1,
+                "properties": {
+                    "all": { "type": "array", "items": { "$ref": "#/definitions/condition" } },
+                    "any": { "type": "array", "items": { "$ref": "#/definitions/condition" } },
+                    "not": { "$ref": "#/definitions/condition" },
+                    "application": { "$ref": "#/definitions/string_or_array" },
+                    "window": { "$ref": "#/definitions/string_or_array" },
+                    "mode": { "$ref": "#/definitions/string_or_array" },
+                    "device": { "$ref": "#/definitions/string_or_array" },
+                    "held": {
+                        "anyOf": [
+                            { "$ref": "#/definitions/key" },
+                            { "type": "array", "items": { "$ref": "#/definitions/key" } },
+                        ],
+                    },
+                },
+            },
+            "modmap": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["remap"],
+                "properties": {
+                    "name": { "type": "string" },
+                    "remap": {
+                        "type": "object",
+                        "propertyNames": { "$ref": "#/definitions/key" },
+                        "additionalProperties": { "$ref": "#/definitions/modmap_action" },
+                    },
+                    "application": { "$ref": "#/definitions/application" },
+                    "window": { "$ref": "#/definitions/window" },
+                    "device": { "$ref": "#/definitions/device" },
+                    "when": { "$ref": "#/definitions/condition" },
+                },
+            },
+            "modmap_action": {
+                "anyOf": [
+                    { "$ref": "#/definitions/key" },
+                    {
+                        "description": "Dispatch different keys depending on whether it's pressed alone or held",
+                        "type": "object",
+                        "additionalProperties": false,
+                        "required": ["held", "alone"],
+                        "properties": {
+                            "held": { "$ref": "#/definitions/key" },
+                            "alone": { "$ref": "#/definitions/key" },
+                            "alone_timeout_millis": { "type": "integer", "minimum": 0, "default": 1000 },
+                        },
+                    },
+                    {
+                        "description": "Dispatch actions depending on how many times the key is tapped",
+                        "type": "object",
+                        "additionalProperties": false,
+                        "required": ["tap_dance"],
+                        "properties": {
+                            "tap_dance": {
+                                "type": "object",
+                                "additionalProperties": false,
+                                "required": ["taps"],
+                                "properties": {
+                                    "taps": {
+                                        "type": "array",
+                                        "minItems": 1,
+                                        "items": { "$ref": "#/definitions/actions" },
+                                    },
+                                    "hold": {
+                                        "description": "A key held while the key is held, or actions dispatched once",
+                                        "anyOf": [
+                                            { "$ref": "#/definitions/key" },
+                                            { "$ref": "#/definitions/actions" },
+                                        ],
+                                    },
+                                    "timeout_millis": { "type": "integer", "minimum": 0, "default": 200 },
+                                },
+                            },
+                        },
+                    },
+                    {
+                        "description": "Hold a modifier for the next key when it's tapped alone",
+                        "type": "object",
+                        "additionalProperties": false,
+                        "required": ["one_shot"],
+                        "properties": {
+                            "one_shot": { "$ref": "#/definitions/key" },
+                            "timeout_millis": { "type": "integer", "minimum": 0 },
+                        },
+                    },
+                    {
+                        "description": "Hook keymap actions on key press/release events",
+                        "type": "object",
+                        "additionalProperties": false,
+                        "required": ["press", "release"],
+                        "properties": {
+                            "press": { "$ref": "#/definitions/actions" },
+                            "release": { "$ref": "#/definitions/actions" },
+                        },
+                    },
+                ],
+            },
+            "keymap": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["remap"],
+                "properties": {
+                    "name": { "type": "string" },
+                    "remap": { "$ref": "#/definitions/remap" },
+                    "application": { "$ref": "#/definitions/application" },
+                    "window": { "$ref": "#/definitions/window" },
+                    "device": { "$ref": "#/definitions/device" },
+                    "when": { "$ref": "#/definitions/condition" },
+                    "mode": { "$ref": "#/definitions/string_or_array" },
+                    "layer": { "type": "string" },
+                    "exact_match": { "type": "boolean", "default": false },
+                },
+            },
+            "combo": {
+                "description": "Dispatch actions when all of the keys are pressed within timeout_millis",
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["keys", "actions"],
+                "properties": {
+                    "keys": { "type": "array", "minItems": 2, "items": { "$ref": "#/definitions/key" } },
+                    "actions": { "$ref": "#/definitions/actions" },
+                    "timeout_millis": { "type": "integer", "minimum": 0, "default": 50 },
+                },
+            },
+            "leader": {
+                "description": "Dispatch actions on sequences of key presses like `leader g s`",
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["sequences"],
+                "properties": {
+                    "key": { "$ref": "#/definitions/key_press" },
+                    "sequences": {
+                        "type": "object",
+                        "additionalProperties": { "$ref": "#/definitions/actions" },
+                    },
+                    "timeout_millis": { "type": "integer", "minimum": 0, "default": 1000 },
+                    "replay": { "type": "boolean", "default": false },
+                },
+            },
+            "remap": {
+                "type": "object",
+                "propertyNames": { "$ref": "#/definitions/key_press" },
+                "additionalProperties": { "$ref": "#/definitions/actions" },
+            },
+            "actions": {
+                "anyOf": [
+                    { "$ref": "#/definitions/keymap_action" },
+                    { "type": "array", "items": { "$ref": "#/definitions/keymap_action" } },
+                ],
+            },
+            "keymap_action": {
+                "anyOf": [
+                    { "$ref": "#/definitions/key_press" },
+                    {
+                        "description": "Remap the next key press",
+                        "type": "object",
+                        "additionalProperties": false,
+                        "required": ["remap"],
+                        "properties": {
+                            "remap": { "$ref": "#/definitions/remap" },
+                            "timeout_millis": { "type": "integer", "minimum": 0 },
+                            "timeout_key": { "$ref": "#/definitions/key" },
+                        },
+                    },
+                    single_key_object("launch", json!({ "type": "array", "items": { "type": "string" } })),
+                    single_key_object("set_mode", json!({ "type": "string" })),
+                    single_key_object("set_mark", json!({ "type": "boolean" })),
+                    single_key_object("with_mark", json!({ "$ref": "#/definitions/key_press" })),
+                    single_key_object("escape_next_key", json!({ "type": "boolean" })),
+                    type_action,
+                    single_key_object("layer_hold", json!({ "type": "string" })),
+                    single_key_object("layer_toggle", json!({ "type": "string" })),
+                    single_key_object("layer_oneshot", json!({ "type": "string" })),
+                    single_key_object("layer_clear", json!({ "type": "boolean" })),
+                    sleep_action,
+                    repeat_action,
+                ],
+            },
+            "repeat_action": {
+                "description": "Actions that can be repeated without changing the state on every iteration",
+                "anyOf": [
+                    { "$ref": "#/definitions/key_press" },
+                    type_action,
+                    sleep_action,
+                    repeat_action,
+                ],
+            },
+        },
+    })
+}
+
+fn single_key_object(key: &str, value: Value) -> Value {
+    json!({
+        "type": "object",
+        "additionalProperties": false,
+        "required": [key],
+        "properties": { key: value },
+    })
+}
+
+// Validate key names case-insensitively, allowing to omit "KEY_"
+fn key_name_pattern(keys: &[String]) -> String {
+    let names: Vec<String> = keys
+        .iter()
+        .map(|key| match key.strip_prefix("KEY_") {
+            Some(name) => format!("(?:{})?{}", case_insensitive("KEY_"), case_insensitive(name)),
+            None => case_insensitive(key),
+        })
+        .collect();
+    names.join("|")
+}
+
+// JSON Schema patterns don't support the `i` flag
+fn case_insensitive(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphabetic() {
+                format!("[{}{}]", c.to_ascii_uppercase(), c.to_ascii_lowercase())
+            } else {
+                c.to_string()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::keymap_action::KEYMAP_ACTION_VARIANTS;
+    use crate::config::modmap_action::MODMAP_ACTION_VARIANTS;
+    use crate::config::{parse_config, ConfigFormat};
+    use jsonschema::JSONSchema;
+    use regex::Regex;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn test_key_press_pattern() {
+        let schema = config_schema();
+        let pattern = schema["definitions"]["key_press"]["pattern"].as_str().unwrap();
+        let regex = Regex::new(pattern).unwrap();
+        for key_press in [
+            "a",
+            "CapsLock",
+            "KEY_GRAVE",
+            "C-M-Shift-a",
+            "Ctrl_L-Enter",
+            "capslock-i",
+            "C-xrightcursor",
+        ] {
+            assert!(regex.is_match(key_press), "{} should match", key_press);
+        }
+        for key_press in ["", "C-", "C-foo", "Hyper-a", "a-"] {
+            assert!(!regex.is_match(key_press), "{} should not match", key_press);
+        }
+    }
+
+    // Configs that xremap accepts should be valid for the schema: the examples and the README's YAML blocks
+    #[test]
+    fn test_valid_configs() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let mut configs = vec![];
+        for entry in fs::read_dir(root.join("example")).unwrap() {
+            let path = entry.unwrap().path();
+            configs.push((path.display().to_string(), fs::read_to_string(&path).unwrap()));
+        }
+        let readme = fs::read_to_string(root.join("README.md")).unwrap();
+        for (index, block) in readme.split("```").enumerate().skip(1).step_by(2) {
+            if let Some(yaml) = block.strip_prefix("yml\n") {
+                // Blocks with placeholders like `MOD1-KEY_XXX`, or only comments, aren't configs
+                let is_map = serde_yaml::from_str::<Value>(yaml).is_ok_and(|value| value.is_object());
+                if is_map && parse_config(yaml, ConfigFormat::Yaml).is_ok() {
+                    configs.push((format!("README.md block #{}", index / 2), yaml.to_string()));
+                }
+            }
+        }
+        assert!(configs.len() > 10, "{}", configs.len());
+
+        let schema = JSONSchema::compile(&config_schema()).unwrap();
+        for (name, yaml) in configs {
+            let config: Value = serde_yaml::from_str(&yaml).unwrap();
+            let errors: Vec<String> = match schema.validate(&config) {
+                Ok(()) => continue,
+                Err(errors) => errors.map(|e| format!("{} at {}", e, e.instance_path)).collect(),
+            };
+            panic!("{} is invalid for the schema:\n{}", name, errors.join("\n"));
+        }
+    }
+
+    // The schema is written by hand, so keep it in sync with the variants listed in error messages
+    #[test]
+    fn test_action_variants() {
+        let schema = config_schema();
+        for (definition, variants) in [
+            ("keymap_action", KEYMAP_ACTION_VARIANTS),
+            ("modmap_action", MODMAP_ACTION_VARIANTS),
+        ] {
+            // The first key of each `{ key: ... }` in the list
+            let mut listed: Vec<&str> = variants
+                .split("{ ")
+                .skip(1)
+                .map(|variant| variant.split(':').next().unwrap())
+                .collect();
+            // The first required key of each object in the schema
+            let mut defined: Vec<&str> = schema["definitions"][definition]["anyOf"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter_map(|variant| variant["required"][0].as_str())
+                .collect();
+            listed.sort();
+            defined.sort();
+            assert_eq!(listed, defined, "{}", definition);
+        }
+    }
+}
@@ -1,6 +1,10 @@
+use crate::config::check::check_config;
+use crate::config::{load_configs, parse_config, ConfigFormat};
 use crate::Config;
+use evdev::Key;
 use indoc::indoc;
-use serde_yaml::Error;
+use std::ffi::OsStr;
+use std::fs;
 
 #[test]
 fn test_modmap_basic() {
@@ -85,6 +89,39 @@ fn test_modmap_press_release_key() {
     "#})
 }
 
+#[test]
+fn test_modmap_tap_dance() {
+    assert_parse(indoc! {r#"
+    modmap:
+      - remap:
+          Esc:
+            tap_dance:
+              taps:
+                - Esc
+                - { launch: ["xdg-screensaver", "lock"] }
+                - [C-a, C-c]
+              hold: Ctrl_L
+              timeout_millis: 300
+          CapsLock:
+            tap_dance:
+              taps: [CapsLock]
+              hold: [C-a, { launch: ["xdg-screensaver", "lock"] }]
+    "#})
+}
+
+#[test]
+fn test_modmap_one_shot() {
+    assert_parse(indoc! {"
+    modmap:
+      - remap:
+          Shift_L:
+            one_shot: Shift_L
+          CapsLock:
+            one_shot: Ctrl_L
+            timeout_millis: 1000
+    "})
+}
+
 #[test]
 fn test_keymap_basic() {
     assert_parse(indoc! {"
@@ -195,9 +232,323 @@ fn test_keymap_mark() {
     "})
 }
 
+#[test]
+fn test_combo() {
+    assert_parse(indoc! {"
+    combo:
+      - keys: [j, k]
+        actions: Esc
+      - keys: [s, d, f]
+        actions: [C-a, { set_mode: normal }]
+        timeout_millis: 80
+    "})
+}
+
+#[test]
+fn test_keymap_layer() {
+    assert_parse(indoc! {"
+    keymap:
+      - remap:
+          CapsLock: { layer_hold: nav }
+          Super-n: { layer_toggle: num }
+          Super-o: { layer_oneshot: nav }
+          Esc: [Esc, { layer_clear: true }]
+      - layer: nav
+        remap:
+          h: left
+    "})
+}
+
+#[test]
+fn test_leader() {
+    assert_parse(indoc! {r#"
+    leader:
+      key: C-Space
+      sequences:
+        leader g s: { launch: ["git", "status"] }
+        leader w: [C-w, { set_mode: window }]
+        C-x C-c: C-q
+      timeout_millis: 500
+      replay: true
+    "#})
+}
+
+#[test]
+fn test_leader_duplicate_sequence() {
+    let yaml = indoc! {"
+    leader:
+      key: C-Space
+      sequences:
+        leader g: C-g
+        C-Space g: C-s
+    "};
+    let error = parse_config(yaml, ConfigFormat::Yaml).unwrap_err();
+    assert!(error.message.contains("duplicate leader sequence"), "{}", error.message);
+}
+
+#[test]
+fn test_keymap_type() {
+    assert_parse(indoc! {"
+    layout:
+      xkb_layout: us
+    unicode_input: ctrl_shift_u
+    keymap:
+      - remap:
+          C-M-s: { type: Best regards → }
+    "})
+}
+
+#[test]
+fn test_keymap_sleep_and_repeat() {
+    assert_parse(indoc! {"
+    keymap:
+      - remap:
+          C-M-s: [C-a, { sleep: 100 }, C-c]
+          C-M-r: { repeat: { times: 3, actions: [down, { sleep: 50 }] } }
+          C-M-t: { repeat: { times: 2, actions: tab } }
+    "});
+
+    // A huge repeat would block the event loop
+    let yaml = indoc! {"
+    keymap:
+      - remap:
+          C-M-r: { repeat: { times: 1000000000, actions: down } }
+    "};
+    assert!(parse_config(yaml, ConfigFormat::Yaml).is_err());
+    let yaml = indoc! {"
+    keymap:
+      - remap:
+          C-M-r: { repeat: { times: 100, actions: { repeat: { times: 100, actions: down } } } }
+    "};
+    assert!(parse_config(yaml, ConfigFormat::Yaml).is_err());
+
+    // Actions changing the state would be run on every iteration
+    assert_parse(indoc! {"
+    keymap:
+      - remap:
+          C-M-r: { repeat: { times: 2, actions: [C-a, { type: ab }, { repeat: { times: 2, actions: tab } }] } }
+    "});
+    let yaml = indoc! {"
+    keymap:
+      - remap:
+          C-M-r: { repeat: { times: 3, actions: { remap: { a: b } } } }
+    "};
+    assert!(parse_config(yaml, ConfigFormat::Yaml).is_err());
+}
+
+#[test]
+fn test_keymap_when() {
+    assert_parse(indoc! {"
+    modmap:
+      - when:
+          any:
+            - device: Ergonomic
+            - held: [Ctrl_L, Alt_L]
+        remap:
+          CapsLock: Esc
+    keymap:
+      - when:
+          all:
+            - mode: normal
+            - not: { application: [Alacritty, /kitty/] }
+            - any:
+                - window: /Jira/
+                - application: Firefox
+        remap:
+          j: Down
+    "})
+}
+
+#[test]
+fn test_include() {
+    let dir = std::env::temp_dir().join(format!("xremap-test-include-{}", std::process::id()));
+    fs::create_dir_all(dir.join("conf.d")).unwrap();
+    fs::write(
+        dir.join("config.yml"),
+        indoc! {"
+        include:
+          - base.yml
+          - conf.d/*.yml
+        keymap:
+          - name: own
+            remap:
+              C-a: home
+        "},
+    )
+    .unwrap();
+    fs::write(
+        dir.join("base.yml"),
+        indoc! {"
+        virtual_modifiers: [CapsLock]
+        keymap:
+          - name: base
+            remap:
+              C-a: C-b
+        "},
+    )
+    .unwrap();
+    fs::write(dir.join("conf.d/b.yml"), "modmap: [{ name: b, remap: { a: b } }]").unwrap();
+    fs::write(dir.join("conf.d/a.yml"), "modmap: [{ name: a, remap: { a: c } }]").unwrap();
+
+    let config = load_configs(&[dir.join("config.yml")]).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let keymap_names: Vec<&str> = config.keymap.iter().map(|keymap| keymap.name.as_str()).collect();
+    assert_eq!(vec!["own", "base"], keymap_names);
+    let modmap_names: Vec<&str> = config.modmap.iter().map(|modmap| modmap.name.as_str()).collect();
+    assert_eq!(vec!["a", "b"], modmap_names);
+    assert_eq!(vec![Key::KEY_CAPSLOCK], config.virtual_modifiers);
+    assert_eq!(4, config.config_files.len());
+    // New files in conf.d are watched for --watch=config
+    assert_eq!(vec![dir.join("conf.d")], config.include_dirs());
+    assert!(config.matches_include_glob(OsStr::new("c.yml")));
+    assert!(!config.matches_include_glob(OsStr::new("c.yml.swp")));
+}
+
+#[test]
+fn test_include_recursive() {
+    let dir = std::env::temp_dir().join(format!("xremap-test-include-recursive-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.yml"), "include: b.yml").unwrap();
+    fs::write(dir.join("b.yml"), "include: a.yml").unwrap();
+
+    let result = load_configs(&[dir.join("a.yml")]);
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(result.unwrap_err().to_string().contains("included recursively"));
+}
+
+#[test]
+fn test_load_configs() {
+    let dir = std::env::temp_dir().join(format!("xremap-test-load-configs-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("base.yml"),
+        indoc! {"
+        default_mode: insert
+        keypress_delay_ms: 10
+        keymap: [{ name: base, remap: { C-a: home } }]
+        "},
+    )
+    .unwrap();
+    fs::write(
+        dir.join("work.yml"),
+        indoc! {"
+        keypress_delay_ms: 20
+        keymap: [{ name: work, remap: { C-e: end } }]
+        "},
+    )
+    .unwrap();
+    fs::write(dir.join("laptop.yml"), "modmap: [{ name: laptop, remap: { CapsLock: Ctrl_L } }]").unwrap();
+
+    let config = load_configs(&[dir.join("base.yml"), dir.join("work.yml"), dir.join("laptop.yml")]).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let keymap_names: Vec<&str> = config.keymap.iter().map(|keymap| keymap.name.as_str()).collect();
+    assert_eq!(vec!["base", "work"], keymap_names);
+    assert_eq!(1, config.modmap.len());
+    assert_eq!("insert", config.default_mode());
+    assert_eq!(20, config.keypress_delay().as_millis());
+    assert_eq!(3, config.config_files.len());
+    assert_eq!(2, config.keymap_table.len());
+}
+
+#[test]
+fn test_load_error_location() {
+    let dir = std::env::temp_dir().join(format!("xremap-test-load-error-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("config.yml"),
+        indoc! {"
+        keymap:
+          - remap:
+              C-a: { lunch: [firefox] }
+        "},
+    )
+    .unwrap();
+
+    let result = load_configs(&[dir.join("config.yml")]);
+    fs::remove_dir_all(&dir).unwrap();
+
+    let message = result.unwrap_err().to_string();
+    assert!(message.contains("config.yml:3:7: keymap[0].remap: "), "{}", message);
+    assert!(message.contains("`{ launch: [...] }`"), "{}", message);
+}
+
+#[test]
+fn test_check_config() {
+    let config: Config = serde_yaml::from_str(indoc! {"
+    keymap:
+      - name: global
+        remap:
+          C-a: home
+          C-x:
+            remap:
+              s: C-s
+            timeout_key: Down
+      - mode: insert
+        remap:
+          Esc: { set_mode: nomal }
+      - exact_match: true
+        remap:
+          C-e: C-end
+      - name: later
+        remap:
+          C-a: end
+          C-e: end
+          C-r: { repeat: { times: 0, actions: up } }
+      - name: firefox
+        application: { only: /firefox/ }
+        remap:
+          C-b: left
+      - name: firefox again
+        application: { only: /firefox/ }
+        remap:
+          C-b: home
+      - name: chrome
+        application: { only: /chrome/ }
+        remap:
+          C-b: home
+    "})
+    .unwrap();
+
+    let mut warnings = check_config(&config);
+    warnings.sort();
+    assert_eq!(
+        vec![
+            "keymap 'firefox again': Control-KEY_B is unreachable because keymap 'firefox' matches it first",
+            "keymap 'later': Control-KEY_A is unreachable because keymap 'global' matches it first",
+            "remap: timeout_key 'KEY_DOWN' is never used since timeout_millis is not set",
+            "repeat: actions are never run since times is 0",
+            "set_mode: mode 'nomal' is not used by any keymap",
+        ],
+        warnings
+    );
+}
+
+#[test]
+fn test_toml_error_location() {
+    let toml = indoc! {r#"
+    [[keymap]]
+    remap = { C-a = { lunch = ["firefox"] } }
+    "#};
+    let error = parse_config(toml, ConfigFormat::Toml).unwrap_err();
+    assert_eq!(Some((2, 17)), error.location);
+    assert!(error.message.contains("untagged enum Actions. Expected"), "{}", error.message);
+}
+
+// Every fixture is also converted to JSON and TOML to test them with the same schema
 fn assert_parse(yaml: &str) {
-    let result: Result<Config, Error> = serde_yaml::from_str(yaml);
-    if let Err(e) = result {
-        panic!("{}", e)
+    let value: serde_yaml::Value = serde_yaml::from_str(yaml).unwrap();
+    let json = serde_json::to_string(&value).unwrap();
+    let toml = toml::to_string(&value).unwrap();
+    for (content, format) in [
+        (yaml, ConfigFormat::Yaml),
+        (&json, ConfigFormat::Json),
+        (&toml, ConfigFormat::Toml),
+    ] {
+        if let Err(e) = parse_config(content, format) {
+            panic!("{:?}: {}\n{}", format, e.message, content)
+        }
     }
 }
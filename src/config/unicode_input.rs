@@ -0,0 +1,152 @@
+use crate::config::key::deserialize_key;
+use crate::config::keysym::keysym_to_char;
+use evdev::Key;
+use serde::{de, Deserialize, Deserializer};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+// How `type` enters characters that the layout can't type
+#[derive(Debug)]
+pub enum UnicodeInput {
+    // Ctrl+Shift+U, the code point in hex, and Space. Supported by GTK and IBus.
+    CtrlShiftU,
+    // The compose key followed by a sequence found in the Compose file
+    Compose {
+        key: Key,
+        sequences: HashMap<char, Vec<char>>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum UnicodeInputConfig {
+    Method(String),
+    Compose(ComposeConfig),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ComposeConfig {
+    #[serde(deserialize_with = "deserialize_key")]
+    compose: Key,
+    compose_file: Option<PathBuf>,
+}
+
+impl<'de> Deserialize<'de> for UnicodeInput {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match UnicodeInputConfig::deserialize(deserializer) {
+            Ok(UnicodeInputConfig::Method(method)) if method == "ctrl_shift_u" => Ok(UnicodeInput::CtrlShiftU),
+            Ok(UnicodeInputConfig::Compose(config)) => {
+                let path = config.compose_file.unwrap_or_else(default_compose_file);
+                let sequences = load_compose_file(&path)
+                    .map_err(|e| de::Error::custom(format!("failed to read {}: {}", path.display(), e)))?;
+                Ok(UnicodeInput::Compose {
+                    key: config.compose,
+                    sequences,
+                })
+            }
+            _ => Err(de::Error::custom(
+                "expected `ctrl_shift_u` or `{ compose: KEY, compose_file: PATH }` for unicode_input",
+            )),
+        }
+    }
+}
+
+// The file libX11 reads if ~/.XCompose doesn't exist. Locales other than en_US aren't resolved.
+const SYSTEM_COMPOSE_FILE: &str = "/usr/share/X11/locale/en_US.UTF-8/Compose";
+
+fn default_compose_file() -> PathBuf {
+    if let Some(path) = env::var_os("XCOMPOSEFILE") {
+        return PathBuf::from(path);
+    }
+    if let Some(home) = env::var_os("HOME") {
+        let path = Path::new(&home).join(".XCompose");
+        if path.exists() {
+            return path;
+        }
+    }
+    PathBuf::from(SYSTEM_COMPOSE_FILE)
+}
+
+// Map characters to the shortest `<Multi_key>` sequence typing them. Sequences using keysyms
+// that aren't characters, like dead keys, are skipped.
+fn load_compose_file(path: &Path) -> Result<HashMap<char, Vec<char>>, std::io::Error> {
+    let mut sequences: HashMap<char, Vec<char>> = HashMap::new();
+    for line in fs::read_to_string(path)?.lines() {
+        let line = line.trim();
+        if let Some(include) = line.strip_prefix("include") {
+            let include = include.trim().trim_matches('"');
+            let include = match include {
+                "%L" => PathBuf::from(SYSTEM_COMPOSE_FILE),
+                _ => PathBuf::from(include.replace("%H", &env::var("HOME").unwrap_or_default())),
+            };
+            for (c, sequence) in load_compose_file(&include)? {
+                insert_shortest(&mut sequences, c, sequence);
+            }
+        } else if let Some((c, sequence)) = parse_compose_line(line) {
+            insert_shortest(&mut sequences, c, sequence);
+        }
+    }
+    Ok(sequences)
+}
+
+fn insert_shortest(sequences: &mut HashMap<char, Vec<char>>, c: char, sequence: Vec<char>) {
+    match sequences.entry(c) {
+        Entry::Occupied(mut existing) => {
+            if existing.get().len() > sequence.len() {
+                existing.insert(sequence);
+            }
+        }
+        Entry::Vacant(entry) => {
+            entry.insert(sequence);
+        }
+    }
+}
+
+// `<Multi_key> <minus> <greater> : "→" U2192 # RIGHTWARDS ARROW`
+fn parse_compose_line(line: &str) -> Option<(char, Vec<char>)> {
+    let (keysyms, result) = line.split_once(':')?;
+    let mut keysyms = keysyms.split_whitespace();
+    if keysyms.next()? != "<Multi_key>" {
+        return None;
+    }
+    let sequence = keysyms
+        .map(|keysym| keysym.strip_prefix('<')?.strip_suffix('>').and_then(keysym_to_char))
+        .collect::<Option<Vec<char>>>()?;
+
+    let result = result.trim().strip_prefix('"')?;
+    let mut chars = result.chars();
+    let c = match chars.next()? {
+        '\\' => chars.next()?,
+        c => c,
+    };
+    if chars.next()? != '"' || sequence.is_empty() {
+        return None;
+    }
+    Some((c, sequence))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_compose_line() {
+        assert_eq!(
+            Some(('→', vec!['-', '>'])),
+            parse_compose_line("<Multi_key> <minus> <greater>\t\t: \"→\"\tU2192 # RIGHTWARDS ARROW")
+        );
+        assert_eq!(
+            Some(('"', vec!['"', '"'])),
+            parse_compose_line("<Multi_key> <quotedbl> <quotedbl> : \"\\\"\" quotedbl")
+        );
+        assert_eq!(None, parse_compose_line("<dead_tilde> <space> : \"~\" asciitilde"));
+        assert_eq!(None, parse_compose_line("<Multi_key> <dead_acute> <e> : \"é\" eacute"));
+        assert_eq!(None, parse_compose_line("# comment"));
+    }
+}
@@ -0,0 +1,295 @@
+use anyhow::{bail, Context};
+use nix::sys::stat::{umask, Mode};
+use nix::unistd::getuid;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::{env, fs};
+
+// A line of JSON sent to the control socket, e.g. `{"command":"set_mode","mode":"normal"}`
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    GetMode,
+    SetMode { mode: String },
+    Pause,
+    Resume,
+    Reload,
+    Devices,
+    State,
+    // Keep the connection open and receive status events
+    Subscribe,
+}
+
+// A line of JSON replied to each Request
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Response {
+    pub fn ok(result: Value) -> Response {
+        Response {
+            ok: true,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    pub fn error(error: impl ToString) -> Response {
+        Response {
+            ok: false,
+            result: None,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+// `$XDG_RUNTIME_DIR/xremap.sock`, or `/tmp/xremap-$UID/xremap.sock` if it's not set, e.g. under sudo
+pub fn default_socket_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => Path::new(&dir).join("xremap.sock"),
+        None => PathBuf::from(format!("/tmp/xremap-{}", getuid())).join("xremap.sock"),
+    }
+}
+
+pub struct ControlSocket {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl ControlSocket {
+    pub fn bind(path: &Path) -> anyhow::Result<ControlSocket> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                bail!("another xremap is listening on {}", path.display());
+            }
+            fs::remove_file(path).with_context(|| format!("removing a stale socket {}", path.display()))?;
+        }
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            prepare_socket_dir(dir)?;
+        }
+        // Only the user running xremap can control it. Set the mode on creation so that nobody connects before chmod.
+        // umask is process-wide, which is fine since xremap binds the socket at startup before any thread exists.
+        let old_umask = umask(Mode::from_bits_truncate(0o177));
+        let listener = UnixListener::bind(path);
+        umask(old_umask);
+        let listener = listener.with_context(|| format!("binding {}", path.display()))?;
+        listener.set_nonblocking(true)?;
+        println!("control socket: {}", path.display());
+        Ok(ControlSocket {
+            listener,
+            path: path.to_path_buf(),
+        })
+    }
+
+    // Read a request from each pending connection, and reply to it with the handler.
+    // Returns the connections that subscribed to status events.
+    pub fn handle_requests(&self, mut handler: impl FnMut(Request) -> Response) -> Vec<UnixStream> {
+        let deadline = Instant::now() + CLIENT_TIMEOUT;
+        let mut subscribers = vec![];
+        // The rest are accepted after the event loop handles input, since the listener stays readable
+        for _ in 0..MAX_CONNECTIONS {
+            if Instant::now() >= deadline {
+                break;
+            }
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return subscribers,
+                Err(e) => {
+                    println!("Failed to accept a control connection: {e}");
+                    return subscribers;
+                }
+            };
+            match handle_connection(stream, deadline, &mut handler) {
+                Ok(Some(subscriber)) => subscribers.push(subscriber),
+                Ok(None) => {}
+                Err(e) => println!("Failed to handle a control connection: {e}"),
+            }
+        }
+        subscribers
+    }
+}
+
+// Create the directory only the user can use, or refuse one where other users could replace the socket,
+// e.g. the predictable fallback directory made in /tmp by someone else.
+fn prepare_socket_dir(dir: &Path) -> anyhow::Result<()> {
+    if !dir.exists() {
+        fs::DirBuilder::new()
+            .mode(0o700)
+            .create(dir)
+            .with_context(|| format!("creating {}", dir.display()))?;
+    }
+    let metadata = fs::metadata(dir).with_context(|| format!("checking {}", dir.display()))?;
+    let uid = getuid().as_raw();
+    // Writable by others without the sticky bit, like /tmp has
+    let writable_by_others = metadata.mode() & 0o022 != 0 && metadata.mode() & 0o1000 == 0;
+    if (metadata.uid() != uid && metadata.uid() != 0) || writable_by_others {
+        bail!("{} is writable by other users; use --socket to choose another path", dir.display());
+    }
+    Ok(())
+}
+
+impl AsRawFd for ControlSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.listener.as_raw_fd()
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// Key events are blocked while clients are being served, so don't wait for slow ones for long.
+// The timeout is for all connections served at a wake-up of the event loop, not for each read.
+const CLIENT_TIMEOUT: Duration = Duration::from_millis(100);
+// Connections served per wake-up of the event loop
+const MAX_CONNECTIONS: usize = 8;
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+
+// Serve a single request per connection
+fn handle_connection(
+    mut stream: UnixStream,
+    deadline: Instant,
+    handler: &mut impl FnMut(Request) -> Response,
+) -> anyhow::Result<Option<UnixStream>> {
+    stream.set_nonblocking(false)?;
+    let line = read_line(&mut stream, deadline)?;
+    let response = match serde_json::from_str::<Request>(&line) {
+        Ok(Request::Subscribe) => return Ok(Some(stream)),
+        Ok(request) => handler(request),
+        Err(e) => Response::error(format!("invalid request: {e}")),
+    };
+    let timeout = deadline.saturating_duration_since(Instant::now());
+    stream.set_write_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+    writeln!(stream, "{}", serde_json::to_string(&response)?)?;
+    Ok(None)
+}
+
+// Read up to the first newline or EOF by the deadline
+fn read_line(stream: &mut UnixStream, deadline: Instant) -> anyhow::Result<String> {
+    let mut line = vec![];
+    let mut buf = [0; 1024];
+    while !line.contains(&b'\n') {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        if timeout.is_zero() {
+            bail!("timed out reading a request");
+        }
+        stream.set_read_timeout(Some(timeout))?;
+        let size = match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(size) => size,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                bail!("timed out reading a request")
+            }
+            Err(e) => return Err(e.into()),
+        };
+        line.extend_from_slice(&buf[..size]);
+        if line.len() > MAX_REQUEST_SIZE {
+            bail!("too long request");
+        }
+    }
+    let line = String::from_utf8(line)?;
+    Ok(line.lines().next().unwrap_or_default().to_string())
+}
+
+// Send a request to a running xremap for `xremap ctl`
+pub fn send_request(path: &Path, request: &Request) -> anyhow::Result<Response> {
+    let mut stream = UnixStream::connect(path).with_context(|| format!("connecting to {}", path.display()))?;
+    writeln!(stream, "{}", serde_json::to_string(request)?)?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    Ok(serde_json::from_str(&line)?)
+}
+
+// Print status events from a running xremap until it exits, for `xremap ctl subscribe`
+pub fn subscribe(path: &Path) -> anyhow::Result<()> {
+    let mut stream = UnixStream::connect(path).with_context(|| format!("connecting to {}", path.display()))?;
+    writeln!(stream, "{}", serde_json::to_string(&Request::Subscribe)?)?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+    let stdout = std::io::stdout();
+    for line in BufReader::new(stream).lines() {
+        // Flush each line for status bars reading it through a pipe
+        let mut stdout = stdout.lock();
+        writeln!(stdout, "{}", line?)?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_response() {
+        let path = env::temp_dir().join(format!("xremap-test-{}.sock", std::process::id()));
+        let socket = ControlSocket::bind(&path).unwrap();
+        let client = std::thread::spawn({
+            let path = path.clone();
+            move || send_request(&path, &Request::SetMode { mode: "insert".into() }).unwrap()
+        });
+        let mut requests = vec![];
+        while requests.is_empty() {
+            socket.handle_requests(|request| {
+                requests.push(format!("{:?}", request));
+                Response::ok(Value::from("insert"))
+            });
+        }
+        let response = client.join().unwrap();
+        assert_eq!(vec!["SetMode { mode: \"insert\" }"], requests);
+        assert!(response.ok);
+        assert_eq!(Some(Value::from("insert")), response.result);
+
+        // The second instance can't take over the socket
+        assert!(ControlSocket::bind(&path).is_err());
+        drop(socket);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_slow_client() {
+        let path = env::temp_dir().join(format!("xremap-test-slow-{}.sock", std::process::id()));
+        let socket = ControlSocket::bind(&path).unwrap();
+        assert_eq!(0o600, fs::metadata(&path).unwrap().mode() & 0o777);
+
+        // Clients trickling bytes can't keep the event loop serving them, even with many connections
+        let trickles: Vec<_> = (0..MAX_CONNECTIONS)
+            .map(|_| {
+                let mut client = UnixStream::connect(&path).unwrap();
+                std::thread::spawn(move || {
+                    for _ in 0..20 {
+                        if client.write_all(b" ").is_err() {
+                            break;
+                        }
+                        std::thread::sleep(Duration::from_millis(20));
+                    }
+                })
+            })
+            .collect();
+        let start = Instant::now();
+        let mut requests = 0;
+        socket.handle_requests(|_| {
+            requests += 1;
+            Response::ok(Value::Null)
+        });
+        assert!(start.elapsed() < Duration::from_millis(300), "{:?}", start.elapsed());
+        assert_eq!(0, requests);
+        drop(socket);
+        for trickle in trickles {
+            trickle.join().unwrap();
+        }
+    }
+}
@@ -6,6 +6,7 @@ use derive_where::derive_where;
 use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
 use evdev::{AttributeSet, BusType, Device, FetchEventsSynced, InputId, Key, RelativeAxisType};
 use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::error::Error;
 use std::fs::read_dir;
@@ -196,6 +197,45 @@ impl InputDevice {
     pub fn bus_type(&self) -> BusType {
         self.device.input_id().bus_type()
     }
+
+    pub fn to_info(&self) -> InputDeviceInfo {
+        InputDeviceInfo {
+            name: self.device_name().to_string(),
+            path: self.path.clone(),
+        }
+    }
+}
+
+// Identity of the device an event came from
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InputDeviceInfo {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl InputDeviceInfo {
+    // Match a device by its name or path, used by --device, --ignore, and `device` in the config
+    pub fn matches(&self, filter: &[String]) -> bool {
+        for device_opt in filter {
+            let device_opt = device_opt.as_str();
+
+            // Check exact matches for explicit selection
+            if self.path.as_os_str() == device_opt || self.name == device_opt {
+                return true;
+            }
+            // eventXX shorthand for /dev/input/eventXX
+            if device_opt.starts_with("event")
+                && self.path.file_name().expect("every device path has a file name") == device_opt
+            {
+                return true;
+            }
+            // Allow partial matches for device names
+            if self.name.contains(device_opt) {
+                return true;
+            }
+        }
+        false
+    }
 }
 
 impl InputDevice {
@@ -227,26 +267,7 @@ impl InputDevice {
         if self.device_name() == Self::current_name() {
             return false;
         }
-
-        for device_opt in filter {
-            let device_opt = device_opt.as_str();
-
-            // Check exact matches for explicit selection
-            if self.path.as_os_str() == device_opt || self.device_name() == device_opt {
-                return true;
-            }
-            // eventXX shorthand for /dev/input/eventXX
-            if device_opt.starts_with("event")
-                && self.path.file_name().expect("every device path has a file name") == device_opt
-            {
-                return true;
-            }
-            // Allow partial matches for device names
-            if self.device_name().contains(device_opt) {
-                return true;
-            }
-        }
-        false
+        self.to_info().matches(filter)
     }
 
     fn is_keyboard(&self) -> bool {
@@ -1,15 +1,19 @@
+use crate::device::InputDeviceInfo;
 use evdev::{EventType, InputEvent, Key};
+use std::rc::Rc;
 
 // Input to EventHandler. This should only contain things that are easily testable.
+// The device is shared by all events fetched from it at once.
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
 pub enum Event {
     // InputEvent (EventType::KEY) sent from evdev
-    KeyEvent(KeyEvent),
+    KeyEvent(Rc<InputDeviceInfo>, KeyEvent),
     // InputEvent (EventType::Relative) sent from evdev
-    RelativeEvent(RelativeEvent),
+    RelativeEvent(Rc<InputDeviceInfo>, RelativeEvent),
     // Any other InputEvent type sent from evdev
     OtherEvents(InputEvent),
-    // Timer for nested override reached its timeout
+    // Timer for nested override or tap-dance keys reached its timeout
     OverrideTimeout,
 }
 
@@ -33,10 +37,10 @@ pub enum KeyValue {
 }
 impl Event {
     // Convert evdev's raw InputEvent to xremap's internal Event
-    pub fn new(event: InputEvent) -> Event {
+    pub fn new(device: Rc<InputDeviceInfo>, event: InputEvent) -> Event {
         let event = match event.event_type() {
-            EventType::KEY => Event::KeyEvent(KeyEvent::new_with(event.code(), event.value())),
-            EventType::RELATIVE => Event::RelativeEvent(RelativeEvent::new_with(event.code(), event.value())),
+            EventType::KEY => Event::KeyEvent(device, KeyEvent::new_with(event.code(), event.value())),
+            EventType::RELATIVE => Event::RelativeEvent(device, RelativeEvent::new_with(event.code(), event.value())),
             _ => Event::OtherEvents(event),
         };
         event
@@ -1,18 +1,23 @@
 use crate::action::Action;
 use crate::client::WMClient;
+use crate::clock::{Clock, Timer};
 use crate::config::application::Application;
+use crate::config::combo::Combo;
+use crate::config::condition::Condition;
 use crate::config::key_press::{KeyPress, Modifier};
 use crate::config::keymap::{build_override_table, OverrideEntry};
 use crate::config::keymap_action::KeymapAction;
-use crate::config::modmap_action::{ModmapAction, MultiPurposeKey, PressReleaseKey};
+use crate::config::modmap_action::{ModmapAction, MultiPurposeKey, OneShot, PressReleaseKey, TapDance, TapDanceHold};
 use crate::config::remap::Remap;
+use crate::config::unicode_input::UnicodeInput;
+use crate::device::InputDeviceInfo;
 use crate::event::{Event, KeyEvent, RelativeEvent};
+use crate::status::Status;
 use crate::Config;
 use evdev::Key;
 use lazy_static::lazy_static;
-use log::debug;
-use nix::sys::time::TimeSpec;
-use nix::sys::timerfd::{Expiration, TimerFd, TimerSetTimeFlags};
+use log::{debug, warn};
+use serde_json::json;
 use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::time::{Duration, Instant};
@@ -33,22 +38,50 @@ pub struct EventHandler {
     // Check the currently active application
     application_client: WMClient,
     application_cache: Option<String>,
+    window_cache: Option<String>,
     // State machine for multi-purpose keys
     multi_purpose_keys: HashMap<Key, MultiPurposeKeyState>,
+    // State machine for tap-dance keys
+    tap_dance_keys: HashMap<Key, TapDanceState>,
+    // State machine for one-shot keys
+    one_shot_keys: HashMap<Key, OneShotState>,
+    // Presses buffered until they're decided to be a combo or not
+    combo_keys: Vec<(Key, InputDeviceInfo)>,
+    combo_timeout_at: Option<Instant>,
+    // Keys used by a combo, whose repeats and releases are swallowed
+    combo_consumed_keys: HashSet<Key>,
+    // Indexes of the leader trie matched so far, and the key presses swallowed for them
+    leader_path: Vec<usize>,
+    leader_keys: Vec<KeyPress>,
+    leader_timeout_at: Option<Instant>,
     // Current nested remaps
     override_remaps: Vec<HashMap<Key, Vec<OverrideEntry>>>,
     // Key triggered on a timeout of nested remaps
     override_timeout_key: Option<Key>,
-    // Trigger a timeout of nested remaps through select(2)
-    override_timer: TimerFd,
+    // When nested remaps time out
+    override_timeout_at: Option<Instant>,
+    // Trigger a timeout of nested remaps or tap-dance keys through select(2)
+    override_timer: Box<dyn Timer>,
+    // Read the time through this instead of Instant::now()
+    clock: Box<dyn Clock>,
     // { set_mode: String }
     mode: String,
+    // Active layers from the bottom to the top
+    layers: Vec<Layer>,
     // { set_mark: true }
     mark_set: bool,
     // { escape_next_key: true }
     escape_next_key: bool,
     // keypress_delay_ms
     keypress_delay: Duration,
+    // Physically pressed keys before any remapping, to detect the emergency chord
+    raw_pressed_keys: HashSet<Key>,
+    // When the emergency chord has been held long enough
+    emergency_at: Option<Instant>,
+    // The emergency chord fired, and devices should be ungrabbed
+    emergency: bool,
+    // Keys pressed on the output device, released on the emergency chord
+    output_pressed_keys: HashSet<Key>,
     // Buffered actions to be dispatched. TODO: Just return actions from each function instead of using this.
     actions: Vec<Action>,
 }
@@ -59,41 +92,64 @@ struct TaggedAction {
 }
 
 impl EventHandler {
-    pub fn new(timer: TimerFd, mode: &str, keypress_delay: Duration, application_client: WMClient) -> EventHandler {
+    pub fn new(
+        timer: Box<dyn Timer>,
+        clock: Box<dyn Clock>,
+        mode: &str,
+        keypress_delay: Duration,
+        application_client: WMClient,
+    ) -> EventHandler {
         EventHandler {
             modifiers: HashSet::new(),
             extra_modifiers: HashSet::new(),
             pressed_keys: HashMap::new(),
             application_client,
             application_cache: None,
+            window_cache: None,
             multi_purpose_keys: HashMap::new(),
+            tap_dance_keys: HashMap::new(),
+            one_shot_keys: HashMap::new(),
+            combo_keys: vec![],
+            combo_timeout_at: None,
+            combo_consumed_keys: HashSet::new(),
+            leader_path: vec![],
+            leader_keys: vec![],
+            leader_timeout_at: None,
             override_remaps: vec![],
             override_timeout_key: None,
+            override_timeout_at: None,
             override_timer: timer,
+            clock,
             mode: mode.to_string(),
+            layers: vec![],
             mark_set: false,
             escape_next_key: false,
             keypress_delay,
+            raw_pressed_keys: HashSet::new(),
+            emergency_at: None,
+            emergency: false,
+            output_pressed_keys: HashSet::new(),
             actions: vec![],
         }
     }
 
-    // Handle an Event and return Actions. This should be the only public method of EventHandler.
+    // Handle an Event and return Actions. Other public methods are only for the control socket.
     pub fn on_events(&mut self, events: &Vec<Event>, config: &Config) -> Result<Vec<Action>, Box<dyn Error>> {
         // a vector to collect mouse movement events to be able to send them all at once as one MouseMovementEventCollection.
         let mut mouse_movement_collection: Vec<RelativeEvent> = Vec::new();
         for event in events {
             match event {
-                Event::KeyEvent(key_event) => {
-                    self.on_key_event(key_event, config)?;
+                Event::KeyEvent(device, key_event) => {
+                    self.watch_emergency_chord(key_event)?;
+                    self.on_key_event(key_event, device, config)?;
                     ()
                 }
-                Event::RelativeEvent(relative_event) => {
-                    self.on_relative_event(relative_event, &mut mouse_movement_collection, config)?
+                Event::RelativeEvent(device, relative_event) => {
+                    self.on_relative_event(relative_event, device, &mut mouse_movement_collection, config)?
                 }
 
                 Event::OtherEvents(event) => self.send_action(Action::InputEvent(*event)),
-                Event::OverrideTimeout => self.timeout_override()?,
+                Event::OverrideTimeout => self.on_timeout(config)?,
             };
         }
         // if there is at least one mouse movement event, sending all of them as one MouseMovementEventCollection
@@ -103,15 +159,139 @@ impl EventHandler {
         Ok(self.actions.drain(..).collect())
     }
 
+    // Release all keys pressed on the output device before exiting, reloading, or pausing
+    pub fn release_all(&mut self) -> Vec<Action> {
+        self.release_all_keys();
+        self.emergency_at = None;
+        self.actions.drain(..).collect()
+    }
+
+    // Keep the mode if the new config still uses it. Call release_all() before this to cancel
+    // anything pending with the old config.
+    pub fn on_config_reload(&mut self, config: &Config) {
+        self.keypress_delay = config.keypress_delay();
+        if !config.modes().contains(&self.mode.as_str()) {
+            println!("mode '{}' is no longer used", self.mode);
+            self.set_mode(config.default_mode());
+        }
+    }
+
+    // Whether the emergency chord fired since the last call
+    pub fn take_emergency(&mut self) -> bool {
+        std::mem::take(&mut self.emergency)
+    }
+
+    pub fn mode(&self) -> &str {
+        &self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: &str) {
+        self.mode = mode.to_string();
+        println!("mode: {}", mode);
+    }
+
+    // The part of the state published to status bars
+    pub fn status(&self, paused: bool) -> Status {
+        Status {
+            mode: self.mode.clone(),
+            override_remap: !self.override_remaps.is_empty(),
+            mark_set: self.mark_set,
+            application: self.application_client.last_application().map(str::to_string),
+            paused,
+        }
+    }
+
+    // Dump the state for debugging
+    pub fn state(&self) -> serde_json::Value {
+        let key_names = |keys: &mut dyn Iterator<Item = &Key>| {
+            let mut names: Vec<String> = keys.map(|key| format!("{:?}", key)).collect();
+            names.sort();
+            names
+        };
+        json!({
+            "mode": self.mode,
+            "layers": self.layers.iter().map(|layer| &layer.name).collect::<Vec<_>>(),
+            "pressed_keys": key_names(&mut self.pressed_keys.keys()),
+            "modifiers": key_names(&mut self.modifiers.iter()),
+            "extra_modifiers": key_names(&mut self.extra_modifiers.iter()),
+            "mark_set": self.mark_set,
+            "escape_next_key": self.escape_next_key,
+            "override_remaps": self
+                .override_remaps
+                .iter()
+                .map(|remap| key_names(&mut remap.keys()))
+                .collect::<Vec<_>>(),
+            "leader_keys": self.leader_keys.iter().map(|key_press| format!("{:?}", key_press.key)).collect::<Vec<_>>(),
+            "application": self.application_cache,
+            "window": self.window_cache,
+        })
+    }
+
     // Handle EventType::KEY
-    fn on_key_event(&mut self, event: &KeyEvent, config: &Config) -> Result<bool, Box<dyn Error>> {
+    fn on_key_event(
+        &mut self,
+        event: &KeyEvent,
+        device: &InputDeviceInfo,
+        config: &Config,
+    ) -> Result<bool, Box<dyn Error>> {
+        let key = Key::new(event.code());
+        if config.combo.is_empty() || key.code() >= DISGUISED_EVENT_OFFSETTER {
+            return self.process_key_event(event, device, config);
+        }
+
+        if event.value() == PRESS && config.combo.iter().any(|combo| combo.keys.contains(&key)) {
+            // A key that no combo can add to the buffered keys decides them first
+            let extendable = config.combo.iter().any(|combo| {
+                combo.keys.contains(&key) && self.combo_keys.iter().all(|(key, _)| combo.keys.contains(key))
+            });
+            if !extendable {
+                self.decide_combo_keys(config)?;
+            }
+            if self.combo_keys.is_empty() {
+                let timeout = config
+                    .combo
+                    .iter()
+                    .filter(|combo| combo.keys.contains(&key))
+                    .map(|combo| combo.timeout)
+                    .max()
+                    .unwrap_or_default();
+                self.combo_timeout_at = Some(self.clock.now() + timeout);
+            }
+            self.combo_keys.push((key, device.clone()));
+            self.update_combo_keys(config)?;
+            return Ok(false);
+        }
+
+        // Any other event decides the buffered keys
+        self.decide_combo_keys(config)?;
+        // Swallow the rest of keys used by a combo
+        if event.value() != PRESS && self.combo_consumed_keys.contains(&key) {
+            if event.value() == RELEASE {
+                self.combo_consumed_keys.remove(&key);
+            }
+            return Ok(false);
+        }
+        self.process_key_event(event, device, config)
+    }
+
+    // Handle EventType::KEY after combos
+    fn process_key_event(
+        &mut self,
+        event: &KeyEvent,
+        device: &InputDeviceInfo,
+        config: &Config,
+    ) -> Result<bool, Box<dyn Error>> {
         self.application_cache = None; // expire cache
+        self.window_cache = None;
         let key = Key::new(event.code());
         debug!("=> {}: {:?}", event.value(), &key);
+        if event.value() == PRESS && !self.tap_dance_keys.is_empty() {
+            self.flush_tap_dance_keys(&key, config)?;
+        }
 
         // Apply modmap
-        let mut key_values = if let Some(key_action) = self.find_modmap(config, &key) {
-            self.dispatch_keys(key_action, key, event.value())?
+        let mut key_values = if let Some(key_action) = self.find_modmap(config, &key, device) {
+            self.dispatch_keys(key_action, key, event.value(), config)?
         } else {
             vec![(key, event.value())]
         };
@@ -120,21 +300,43 @@ impl EventHandler {
             key_values = self.flush_timeout_keys(key_values);
         }
 
+        // One-shot keys are released after the next non-modifier key
+        let release_one_shot_keys = !self.one_shot_keys.is_empty()
+            && key_values.iter().any(|(key, value)| {
+                *value == PRESS
+                    && key.code() < DISGUISED_EVENT_OFFSETTER
+                    && !MODIFIER_KEYS.contains(key)
+                    && !config.virtual_modifiers.contains(key)
+            });
+
         let mut send_original_relative_event = false;
         // Apply keymap
         for (key, value) in key_values.into_iter() {
+            if value == RELEASE {
+                self.release_layers(&key);
+            }
             if config.virtual_modifiers.contains(&key) {
                 self.update_modifier(key, value);
                 continue;
             } else if MODIFIER_KEYS.contains(&key) {
                 self.update_modifier(key, value);
             } else if is_pressed(value) {
+                // A one-shot layer is used by the next key press
+                let oneshot_layers = value == PRESS && self.layers.iter().any(|layer| layer.oneshot);
                 if self.escape_next_key {
                     self.escape_next_key = false
-                } else if let Some(actions) = self.find_keymap(config, &key)? {
-                    self.dispatch_actions(&actions, &key)?;
+                } else if self.on_leader_key(config, &key, value)? {
+                    continue;
+                } else if let Some(actions) = self.find_keymap(config, &key, device)? {
+                    if oneshot_layers {
+                        self.remove_layers(|layer| layer.oneshot);
+                    }
+                    self.dispatch_actions(&actions, &key, value, config)?;
                     continue;
                 }
+                if oneshot_layers {
+                    self.remove_layers(|layer| layer.oneshot);
+                }
             }
             // checking if there's a "disguised" key version of a relative event,
             // (scancodes equal to and over DISGUISED_EVENT_OFFSETTER are only "disguised" custom events)
@@ -146,6 +348,9 @@ impl EventHandler {
             }
             self.send_key(&key, value);
         }
+        if release_one_shot_keys {
+            self.release_one_shot_keys(config)?;
+        }
 
         // Using the Ok() to send a boolean to on_relative_event, which will be used to decide whether to send the original relative event.
         // (True = send the original relative event, false = don't send it.)
@@ -156,6 +361,7 @@ impl EventHandler {
     fn on_relative_event(
         &mut self,
         event: &RelativeEvent,
+        device: &InputDeviceInfo,
         mouse_movement_collection: &mut Vec<RelativeEvent>,
         config: &Config,
     ) -> Result<(), Box<dyn Error>> {
@@ -199,7 +405,7 @@ impl EventHandler {
         };
 
         // Sending a RELATIVE event "disguised" as a "fake" KEY event press to on_key_event.
-        match self.on_key_event(&KeyEvent::new_with(key, PRESS), config)? {
+        match self.on_key_event(&KeyEvent::new_with(key, PRESS), device, config)? {
             // the boolean value is from a variable at the end of on_key_event from event_handler,
             // used to indicate whether the event got through unchanged.
             true => {
@@ -228,8 +434,205 @@ impl EventHandler {
         }
 
         // Sending the "unpressed" version of the "fake" KEY event.
-        self.on_key_event(&KeyEvent::new_with(key, RELEASE), config)?;
+        self.on_key_event(&KeyEvent::new_with(key, RELEASE), device, config)?;
+
+        Ok(())
+    }
+
+    // Handle the timer shared by nested remaps and tap-dance keys
+    fn on_timeout(&mut self, config: &Config) -> Result<(), Box<dyn Error>> {
+        let now = self.clock.now();
+        if self.emergency_at.is_some_and(|emergency_at| emergency_at <= now) {
+            self.emergency_at = None;
+            self.emergency_escape();
+            return self.update_timer();
+        }
+        if self.override_timeout_at.is_some_and(|timeout_at| timeout_at <= now) {
+            self.timeout_override()?;
+        }
+        if self.combo_timeout_at.is_some_and(|timeout_at| timeout_at <= now) {
+            self.decide_combo_keys(config)?;
+        }
+        if self.leader_timeout_at.is_some_and(|timeout_at| timeout_at <= now) {
+            self.finish_leader(config)?;
+        }
+        let keys: Vec<Key> = self
+            .one_shot_keys
+            .iter()
+            .filter(|(_, state)| state.timeout_at.is_some_and(|timeout_at| timeout_at <= now))
+            .map(|(key, _)| *key)
+            .collect();
+        for key in keys {
+            let state = self.one_shot_keys.remove(&key).unwrap();
+            self.send_modifier_key(state.modifier, RELEASE, config);
+        }
+        let keys: Vec<Key> = self
+            .tap_dance_keys
+            .iter()
+            .filter(|(_, state)| !state.holding && state.timeout_at <= now)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in keys {
+            self.resolve_tap_dance(key, config)?;
+        }
+        self.update_timer()
+    }
+
+    // The earliest time at which Event::OverrideTimeout should be sent
+    fn next_timeout(&self) -> Option<Instant> {
+        self.tap_dance_keys
+            .values()
+            .filter(|state| !state.holding)
+            .map(|state| state.timeout_at)
+            .chain(self.override_timeout_at)
+            .chain(self.combo_timeout_at)
+            .chain(self.leader_timeout_at)
+            .chain(self.one_shot_keys.values().filter_map(|state| state.timeout_at))
+            .chain(self.emergency_at)
+            .min()
+    }
+
+    // Arm the timer for the earliest timeout, or disarm it if nothing is waiting
+    fn update_timer(&mut self) -> Result<(), Box<dyn Error>> {
+        self.override_timer
+            .set_deadline(self.next_timeout(), self.clock.now())?;
+        Ok(())
+    }
+
+    // Dispatch a combo if all of its keys are pressed and no longer combo can match them,
+    // or replay the keys if no combo can match them
+    fn update_combo_keys(&mut self, config: &Config) -> Result<(), Box<dyn Error>> {
+        let keys: Vec<Key> = self.combo_keys.iter().map(|(key, _)| *key).collect();
+        let candidates: Vec<&Combo> = config
+            .combo
+            .iter()
+            .filter(|combo| keys.iter().all(|key| combo.keys.contains(key)))
+            .collect();
+        if candidates.is_empty() {
+            return self.flush_combo_keys(config);
+        }
+        // e.g. [j, k] waits for the timeout or another event while [j, k, l] can still match
+        if candidates.iter().all(|combo| combo.keys.len() == keys.len()) {
+            return self.decide_combo_keys(config);
+        }
+        self.update_timer()
+    }
+
+    // Dispatch the combo completed by the buffered keys, or replay them if there's none
+    fn decide_combo_keys(&mut self, config: &Config) -> Result<(), Box<dyn Error>> {
+        let keys: Vec<Key> = self.combo_keys.iter().map(|(key, _)| *key).collect();
+        let completed = config
+            .combo
+            .iter()
+            .find(|combo| combo.keys.len() == keys.len() && keys.iter().all(|key| combo.keys.contains(key)));
+        if let Some(combo) = completed {
+            self.combo_keys.clear();
+            self.combo_timeout_at = None;
+            self.combo_consumed_keys.extend(keys.iter());
+            let actions: Vec<TaggedAction> = combo
+                .actions
+                .iter()
+                .map(|action| TaggedAction {
+                    action: action.clone(),
+                    exact_match: false,
+                })
+                .collect();
+            self.dispatch_actions(&actions, &keys[keys.len() - 1], PRESS, config)?;
+            self.update_timer()
+        } else {
+            self.flush_combo_keys(config)
+        }
+    }
+
+    // Replay buffered presses as if there were no combo
+    fn flush_combo_keys(&mut self, config: &Config) -> Result<(), Box<dyn Error>> {
+        if self.combo_keys.is_empty() {
+            return Ok(());
+        }
+        self.combo_timeout_at = None;
+        self.update_timer()?;
+        for (key, device) in std::mem::take(&mut self.combo_keys) {
+            self.process_key_event(&KeyEvent::new_with(key.code(), PRESS), &device, config)?;
+        }
+        Ok(())
+    }
 
+    // Follow the leader trie with a key press. Return true if the key is swallowed by a sequence.
+    fn on_leader_key(&mut self, config: &Config, key: &Key, value: i32) -> Result<bool, Box<dyn Error>> {
+        let leader = match &config.leader {
+            Some(leader) => leader,
+            None => return Ok(false),
+        };
+        if self.leader_path.is_empty() && !self.override_remaps.is_empty() {
+            return Ok(false); // let find_keymap handle nested remaps
+        }
+        if value != PRESS {
+            return Ok(!self.leader_path.is_empty()); // swallow repeats in the middle of a sequence
+        }
+
+        let node = leader.sequences.get(&self.leader_path);
+        let child = [true, false].into_iter().find_map(|exact_match| {
+            node.children.iter().position(|(key_press, _)| {
+                let (extra_modifiers, missing_modifiers) = self.diff_modifiers(&key_press.modifiers);
+                key_press.key == *key && missing_modifiers.is_empty() && (!exact_match || extra_modifiers.is_empty())
+            })
+        });
+        match child {
+            Some(index) => {
+                self.leader_path.push(index);
+                self.leader_keys.push(KeyPress {
+                    key: *key,
+                    modifiers: self.modifiers.iter().map(|modifier| Modifier::Key(*modifier)).collect(),
+                });
+                if node.children[index].1.children.is_empty() {
+                    self.finish_leader(config)?;
+                } else {
+                    // Wait for the next key even if the sequence is already complete here
+                    self.leader_timeout_at = Some(self.clock.now() + leader.timeout);
+                    self.update_timer()?;
+                }
+                Ok(true)
+            }
+            None if self.leader_path.is_empty() => Ok(false),
+            None => {
+                // A mismatch ends the sequence, and then the key may start another one
+                self.finish_leader(config)?;
+                self.on_leader_key(config, key, value)
+            }
+        }
+    }
+
+    // Dispatch the actions of the sequence matched so far, or cancel it if it's incomplete
+    fn finish_leader(&mut self, config: &Config) -> Result<(), Box<dyn Error>> {
+        let path = std::mem::take(&mut self.leader_path);
+        let keys = std::mem::take(&mut self.leader_keys);
+        self.leader_timeout_at = None;
+        self.update_timer()?;
+
+        let leader = match &config.leader {
+            Some(leader) => leader,
+            None => return Ok(()),
+        };
+        match &leader.sequences.get(&path).actions {
+            Some(actions) => {
+                let actions: Vec<TaggedAction> = actions
+                    .iter()
+                    .map(|action| TaggedAction {
+                        action: action.clone(),
+                        exact_match: false,
+                    })
+                    .collect();
+                if let Some(key_press) = keys.last() {
+                    self.dispatch_actions(&actions, &key_press.key, PRESS, config)?;
+                }
+            }
+            None if leader.replay => {
+                for key_press in &keys {
+                    self.send_key_press(key_press);
+                }
+            }
+            None => {}
+        }
         Ok(())
     }
 
@@ -242,10 +645,10 @@ impl EventHandler {
     }
 
     fn remove_override(&mut self) -> Result<(), Box<dyn Error>> {
-        self.override_timer.unset()?;
         self.override_remaps.clear();
         self.override_timeout_key = None;
-        Ok(())
+        self.override_timeout_at = None;
+        self.update_timer()
     }
 
     fn send_keys(&mut self, keys: &Vec<Key>, value: i32) {
@@ -261,9 +664,74 @@ impl EventHandler {
     }
 
     fn send_action(&mut self, action: Action) {
+        if let Action::KeyEvent(event) = &action {
+            let key = Key::new(event.code());
+            if event.value() == RELEASE {
+                self.output_pressed_keys.remove(&key);
+            } else {
+                self.output_pressed_keys.insert(key);
+            }
+        }
         self.actions.push(action);
     }
 
+    // Start or cancel the timer for Ctrl+Alt+Shift+Esc, which works regardless of the config
+    fn watch_emergency_chord(&mut self, event: &KeyEvent) -> Result<(), Box<dyn Error>> {
+        let key = Key::new(event.code());
+        if event.value() == PRESS {
+            self.raw_pressed_keys.insert(key);
+        } else if event.value() == RELEASE {
+            self.raw_pressed_keys.remove(&key);
+        } else {
+            return Ok(());
+        }
+        let chord_held = EMERGENCY_CHORD
+            .iter()
+            .all(|keys| keys.iter().any(|key| self.raw_pressed_keys.contains(key)));
+        match (chord_held, self.emergency_at) {
+            (true, None) => self.emergency_at = Some(self.clock.now() + EMERGENCY_HOLD),
+            (false, Some(_)) => self.emergency_at = None,
+            _ => return Ok(()),
+        }
+        self.update_timer()
+    }
+
+    // Release everything pressed on the output device and forget the remapping state
+    fn release_all_keys(&mut self) {
+        let mut keys: Vec<Key> = self.output_pressed_keys.iter().copied().collect();
+        keys.sort_by_key(|key| key.code());
+        for key in keys {
+            self.send_key(&key, RELEASE);
+        }
+        self.modifiers.clear();
+        self.extra_modifiers.clear();
+        self.pressed_keys.clear();
+        // Multi-purpose keys being held haven't pressed anything yet
+        self.multi_purpose_keys.clear();
+        self.tap_dance_keys.clear();
+        self.one_shot_keys.clear();
+        self.combo_keys.clear();
+        self.combo_timeout_at = None;
+        self.combo_consumed_keys.clear();
+        self.leader_path.clear();
+        self.leader_keys.clear();
+        self.leader_timeout_at = None;
+        self.override_remaps.clear();
+        self.override_timeout_key = None;
+        self.override_timeout_at = None;
+        self.layers.clear();
+        self.mark_set = false;
+        self.escape_next_key = false;
+    }
+
+    fn emergency_escape(&mut self) {
+        println!("Emergency chord was held. Releasing all keys.");
+        self.release_all_keys();
+        // The physical keys are released while the devices are ungrabbed
+        self.raw_pressed_keys.clear();
+        self.emergency = true;
+    }
+
     // Repeat/Release what's originally pressed even if remapping changes while holding it
     fn maintain_pressed_keys(&mut self, key: Key, value: i32, events: &mut Vec<(Key, i32)>) {
         // Not handling multi-purpose keysfor now; too complicated
@@ -289,6 +757,7 @@ impl EventHandler {
         key_action: ModmapAction,
         key: Key,
         value: i32,
+        config: &Config,
     ) -> Result<Vec<(Key, i32)>, Box<dyn Error>> {
         let keys = match key_action {
             ModmapAction::Key(modmap_key) => vec![(modmap_key, value)],
@@ -303,17 +772,17 @@ impl EventHandler {
                         MultiPurposeKeyState {
                             held,
                             alone,
-                            alone_timeout_at: Some(Instant::now() + alone_timeout),
+                            alone_timeout_at: Some(self.clock.now() + alone_timeout),
                         },
                     );
                     return Ok(vec![]); // delay the press
                 } else if value == REPEAT {
                     if let Some(state) = self.multi_purpose_keys.get_mut(&key) {
-                        return Ok(state.repeat());
+                        return Ok(state.repeat(self.clock.now()));
                     }
                 } else if value == RELEASE {
                     if let Some(state) = self.multi_purpose_keys.remove(&key) {
-                        return Ok(state.release());
+                        return Ok(state.release(self.clock.now()));
                     }
                 } else {
                     panic!("unexpected key event value: {}", value);
@@ -334,15 +803,194 @@ impl EventHandler {
                             })
                             .collect(),
                         &key,
+                        value,
+                        config,
                     )?;
                 }
                 // Dispatch the original key as well
                 vec![(key, value)]
             }
+            ModmapAction::TapDance(tap_dance) => {
+                if value == PRESS {
+                    let state = self.tap_dance_keys.entry(key).or_insert_with(|| TapDanceState {
+                        tap_dance,
+                        taps: 0,
+                        pressed: false,
+                        holding: false,
+                        timeout_at: self.clock.now(),
+                    });
+                    state.taps += 1;
+                    state.pressed = true;
+                    state.timeout_at = self.clock.now() + state.tap_dance.timeout;
+                    self.update_timer()?;
+                } else if value == REPEAT {
+                    if let Some(TapDanceState {
+                        tap_dance:
+                            TapDance {
+                                hold: Some(TapDanceHold::Key(hold)),
+                                ..
+                            },
+                        holding: true,
+                        ..
+                    }) = self.tap_dance_keys.get(&key)
+                    {
+                        return Ok(vec![(*hold, REPEAT)]);
+                    }
+                } else if value == RELEASE {
+                    if let Some(state) = self.tap_dance_keys.get_mut(&key) {
+                        state.pressed = false;
+                        if let (true, Some(TapDanceHold::Key(hold))) = (state.holding, &state.tap_dance.hold) {
+                            let hold = *hold;
+                            self.tap_dance_keys.remove(&key);
+                            return Ok(vec![(hold, RELEASE)]);
+                        } else if state.taps >= state.tap_dance.taps.len() {
+                            // No more taps can make a difference
+                            let state = self.tap_dance_keys.remove(&key).unwrap();
+                            self.dispatch_tap_dance(&state, &key, config)?;
+                        } else {
+                            state.timeout_at = self.clock.now() + state.tap_dance.timeout;
+                        }
+                        self.update_timer()?;
+                    }
+                } else {
+                    panic!("unexpected key event value: {}", value);
+                }
+                // The key itself is never dispatched
+                vec![]
+            }
+            ModmapAction::OneShot(OneShot { one_shot, timeout }) => {
+                if value == PRESS {
+                    if let Some(state) = self.one_shot_keys.get_mut(&key) {
+                        // Still armed. Just keep holding it.
+                        state.pressed = true;
+                        state.used = false;
+                        state.timeout_at = None;
+                        self.update_timer()?;
+                        return Ok(vec![]);
+                    }
+                    self.one_shot_keys.insert(
+                        key,
+                        OneShotState {
+                            modifier: one_shot,
+                            pressed: true,
+                            used: false,
+                            timeout_at: None,
+                        },
+                    );
+                    vec![(one_shot, PRESS)]
+                } else if value == REPEAT {
+                    vec![(one_shot, REPEAT)]
+                } else if value == RELEASE {
+                    match self.one_shot_keys.get_mut(&key) {
+                        Some(state) if state.used => {
+                            let state = self.one_shot_keys.remove(&key).unwrap();
+                            vec![(state.modifier, RELEASE)]
+                        }
+                        Some(state) => {
+                            // Tapped alone. Keep holding it for the next key.
+                            state.pressed = false;
+                            state.timeout_at = timeout.map(|timeout| self.clock.now() + timeout);
+                            self.update_timer()?;
+                            vec![]
+                        }
+                        None => vec![],
+                    }
+                } else {
+                    panic!("unexpected key event value: {}", value);
+                }
+            }
         };
         Ok(keys)
     }
 
+    // Decide pending tap-dance keys when another key is pressed
+    fn flush_tap_dance_keys(&mut self, pressed_key: &Key, config: &Config) -> Result<(), Box<dyn Error>> {
+        let keys: Vec<Key> = self
+            .tap_dance_keys
+            .iter()
+            .filter(|(key, state)| *key != pressed_key && !state.holding)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in keys {
+            self.resolve_tap_dance(key, config)?;
+        }
+        self.update_timer()
+    }
+
+    // Hold `hold` if the key is still pressed. Otherwise dispatch the actions for the taps so far.
+    fn resolve_tap_dance(&mut self, key: Key, config: &Config) -> Result<(), Box<dyn Error>> {
+        let state = match self.tap_dance_keys.get_mut(&key) {
+            Some(state) => state,
+            None => return Ok(()),
+        };
+        match (state.pressed, &state.tap_dance.hold) {
+            (true, Some(TapDanceHold::Key(hold))) => {
+                let hold = *hold;
+                state.holding = true;
+                self.send_modifier_key(hold, PRESS, config);
+            }
+            // The release is swallowed as the state is gone
+            (true, Some(TapDanceHold::Actions(actions))) => {
+                let actions: Vec<TaggedAction> = actions
+                    .iter()
+                    .map(|action| TaggedAction {
+                        action: action.clone(),
+                        exact_match: false,
+                    })
+                    .collect();
+                self.tap_dance_keys.remove(&key);
+                self.dispatch_actions(&actions, &key, PRESS, config)?;
+            }
+            _ => {
+                let state = self.tap_dance_keys.remove(&key).unwrap();
+                self.dispatch_tap_dance(&state, &key, config)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Release armed one-shot keys, and let held ones work as normal modifiers
+    fn release_one_shot_keys(&mut self, config: &Config) -> Result<(), Box<dyn Error>> {
+        let keys: Vec<Key> = self.one_shot_keys.keys().copied().collect();
+        for key in keys {
+            let state = self.one_shot_keys.get_mut(&key).unwrap();
+            if state.pressed {
+                state.used = true;
+            } else {
+                let state = self.one_shot_keys.remove(&key).unwrap();
+                self.send_modifier_key(state.modifier, RELEASE, config);
+            }
+        }
+        self.update_timer()
+    }
+
+    // This skips keymap, but the modifier state is kept for following keys
+    fn send_modifier_key(&mut self, key: Key, value: i32, config: &Config) {
+        if config.virtual_modifiers.contains(&key) || MODIFIER_KEYS.contains(&key) {
+            self.update_modifier(key, value);
+        }
+        if !config.virtual_modifiers.contains(&key) {
+            self.send_key(&key, value);
+        }
+    }
+
+    fn dispatch_tap_dance(&mut self, state: &TapDanceState, key: &Key, config: &Config) -> Result<(), Box<dyn Error>> {
+        let taps = &state.tap_dance.taps;
+        let actions = &taps[state.taps.min(taps.len()) - 1];
+        self.dispatch_actions(
+            &actions
+                .iter()
+                .map(|action| TaggedAction {
+                    action: action.clone(),
+                    exact_match: false,
+                })
+                .collect(),
+            key,
+            PRESS,
+            config,
+        )
+    }
+
     fn flush_timeout_keys(&mut self, key_values: Vec<(Key, i32)>) -> Vec<(Key, i32)> {
         let mut flush = false;
         for (_, value) in key_values.iter() {
@@ -364,7 +1012,7 @@ impl EventHandler {
         }
     }
 
-    fn find_modmap(&mut self, config: &Config, key: &Key) -> Option<ModmapAction> {
+    fn find_modmap(&mut self, config: &Config, key: &Key, device: &InputDeviceInfo) -> Option<ModmapAction> {
         for modmap in &config.modmap {
             if let Some(key_action) = modmap.remap.get(key) {
                 if let Some(application_matcher) = &modmap.application {
@@ -372,13 +1020,33 @@ impl EventHandler {
                         continue;
                     }
                 }
+                if let Some(window_matcher) = &modmap.window {
+                    if !self.match_window(window_matcher) {
+                        continue;
+                    }
+                }
+                if let Some(device_matcher) = &modmap.device {
+                    if !device_matcher.matches(device) {
+                        continue;
+                    }
+                }
+                if let Some(condition) = &modmap.when {
+                    if !self.match_condition(condition, device) {
+                        continue;
+                    }
+                }
                 return Some(key_action.clone());
             }
         }
         None
     }
 
-    fn find_keymap(&mut self, config: &Config, key: &Key) -> Result<Option<Vec<TaggedAction>>, Box<dyn Error>> {
+    fn find_keymap(
+        &mut self,
+        config: &Config,
+        key: &Key,
+        device: &InputDeviceInfo,
+    ) -> Result<Option<Vec<TaggedAction>>, Box<dyn Error>> {
         if !self.override_remaps.is_empty() {
             let entries: Vec<OverrideEntry> = self
                 .override_remaps
@@ -419,10 +1087,21 @@ impl EventHandler {
             self.timeout_override()?;
         }
 
+        // Look up layers from the top, falling through to keymaps without a layer
+        let layers: Vec<Option<String>> = self
+            .layers
+            .iter()
+            .rev()
+            .map(|layer| Some(layer.name.clone()))
+            .chain([None])
+            .collect();
         if let Some(entries) = config.keymap_table.get(key) {
-            for exact_match in [true, false] {
+            for (layer, exact_match) in layers.iter().flat_map(|layer| [(layer, true), (layer, false)]) {
                 let mut remaps = vec![];
                 for entry in entries {
+                    if &entry.layer != layer {
+                        continue;
+                    }
                     if entry.exact_match && !exact_match {
                         continue;
                     }
@@ -435,11 +1114,26 @@ impl EventHandler {
                             continue;
                         }
                     }
+                    if let Some(window_matcher) = &entry.window {
+                        if !self.match_window(window_matcher) {
+                            continue;
+                        }
+                    }
+                    if let Some(device_matcher) = &entry.device {
+                        if !device_matcher.matches(device) {
+                            continue;
+                        }
+                    }
                     if let Some(modes) = &entry.mode {
                         if !modes.contains(&self.mode) {
                             continue;
                         }
                     }
+                    if let Some(condition) = &entry.when {
+                        if !self.match_condition(condition, device) {
+                            continue;
+                        }
+                    }
 
                     let actions = with_extra_modifiers(&entry.actions, &extra_modifiers, entry.exact_match);
                     let is_remap = is_remap(&entry.actions);
@@ -459,14 +1153,27 @@ impl EventHandler {
         Ok(None)
     }
 
-    fn dispatch_actions(&mut self, actions: &Vec<TaggedAction>, key: &Key) -> Result<(), Box<dyn Error>> {
+    // `value` is PRESS or REPEAT of the key triggering the actions
+    fn dispatch_actions(
+        &mut self,
+        actions: &Vec<TaggedAction>,
+        key: &Key,
+        value: i32,
+        config: &Config,
+    ) -> Result<(), Box<dyn Error>> {
         for action in actions {
-            self.dispatch_action(action, key)?;
+            self.dispatch_action(action, key, value, config)?;
         }
         Ok(())
     }
 
-    fn dispatch_action(&mut self, action: &TaggedAction, key: &Key) -> Result<(), Box<dyn Error>> {
+    fn dispatch_action(
+        &mut self,
+        action: &TaggedAction,
+        key: &Key,
+        value: i32,
+        config: &Config,
+    ) -> Result<(), Box<dyn Error>> {
         match &action.action {
             KeymapAction::KeyPress(key_press) => self.send_key_press(key_press),
             KeymapAction::Remap(Remap {
@@ -482,22 +1189,58 @@ impl EventHandler {
                 // so the behaviour is consistent with how current normal keymap override works
                 if set_timeout {
                     if let Some(timeout) = timeout {
-                        let expiration = Expiration::OneShot(TimeSpec::from_duration(*timeout));
                         // TODO: Consider handling the timer in ActionDispatcher
-                        self.override_timer.unset()?;
-                        self.override_timer.set(expiration, TimerSetTimeFlags::empty())?;
+                        self.override_timeout_at = Some(self.clock.now() + *timeout);
                         self.override_timeout_key = timeout_key.or_else(|| Some(*key));
+                        self.update_timer()?;
                     }
                 }
             }
             KeymapAction::Launch(command) => self.run_command(command.clone()),
-            KeymapAction::SetMode(mode) => {
-                self.mode = mode.clone();
-                println!("mode: {}", mode);
-            }
+            KeymapAction::SetMode(mode) => self.set_mode(mode),
             KeymapAction::SetMark(set) => self.mark_set = *set,
             KeymapAction::WithMark(key_press) => self.send_key_press(&self.with_mark(key_press)),
             KeymapAction::EscapeNextKey(escape_next_key) => self.escape_next_key = *escape_next_key,
+            KeymapAction::Type(text) => self.type_text(text, config),
+            KeymapAction::LayerHold(name) => {
+                // Ignore key repeats while the layer is held
+                if !self
+                    .layers
+                    .iter()
+                    .any(|layer| &layer.name == name && layer.held_by == Some(*key))
+                {
+                    self.push_layer(name, Some(*key), false);
+                }
+            }
+            // Ignore key repeats, which would flip the layer back and forth
+            KeymapAction::LayerToggle(_) if value == REPEAT => {}
+            KeymapAction::LayerToggle(name) => {
+                if self.layers.iter().any(|layer| &layer.name == name) {
+                    self.remove_layers(|layer| &layer.name == name);
+                } else {
+                    self.push_layer(name, None, false);
+                }
+            }
+            // Ignore key repeats, which would stack the same layer
+            KeymapAction::LayerOneshot(_) if value == REPEAT => {}
+            KeymapAction::LayerOneshot(name) => self.push_layer(name, None, true),
+            KeymapAction::LayerClear(clear) => {
+                if *clear {
+                    self.remove_layers(|_| true);
+                }
+            }
+            KeymapAction::Sleep(duration) => self.send_action(Action::Delay(*duration)),
+            KeymapAction::Repeat(repeat) => {
+                for _ in 0..repeat.times {
+                    for nested_action in &repeat.actions {
+                        let nested_action = TaggedAction {
+                            action: nested_action.clone(),
+                            exact_match: action.exact_match,
+                        };
+                        self.dispatch_action(&nested_action, key, value, config)?;
+                    }
+                }
+            }
             KeymapAction::SetExtraModifiers(keys) => {
                 self.extra_modifiers.clear();
                 for key in keys {
@@ -530,6 +1273,44 @@ impl EventHandler {
         self.send_keys(&missing_modifiers, RELEASE);
     }
 
+    // Type characters with the layout, or with unicode_input if the layout can't type them
+    fn type_text(&mut self, text: &str, config: &Config) {
+        let layout = config.layout();
+        for c in text.chars() {
+            if let Some(key_press) = layout.key_press(c) {
+                self.send_key_press(key_press);
+                continue;
+            }
+            match config.unicode_input() {
+                UnicodeInput::CtrlShiftU => {
+                    self.send_key_press(&KeyPress {
+                        key: Key::KEY_U,
+                        modifiers: vec![Modifier::Control, Modifier::Shift],
+                    });
+                    for digit in format!("{:x}", c as u32).chars().chain([' ']) {
+                        if let Some(key_press) = layout.key_press(digit) {
+                            self.send_key_press(key_press);
+                        }
+                    }
+                }
+                UnicodeInput::Compose { key, sequences } => match sequences.get(&c) {
+                    Some(sequence) => {
+                        self.send_key_press(&KeyPress {
+                            key: *key,
+                            modifiers: vec![],
+                        });
+                        for c in sequence {
+                            if let Some(key_press) = layout.key_press(*c) {
+                                self.send_key_press(key_press);
+                            }
+                        }
+                    }
+                    None => warn!("type: no compose sequence is found for '{}'", c),
+                },
+            }
+        }
+    }
+
     fn with_mark(&self, key_press: &KeyPress) -> KeyPress {
         if self.mark_set && !self.match_modifier(&Modifier::Shift) {
             let mut modifiers = key_press.modifiers.clone();
@@ -600,16 +1381,67 @@ impl EventHandler {
         }
 
         if let Some(application) = &self.application_cache {
-            if let Some(application_only) = &application_matcher.only {
-                return application_only.iter().any(|m| m.matches(application));
-            }
-            if let Some(application_not) = &application_matcher.not {
-                return application_not.iter().all(|m| !m.matches(application));
+            return match_matchers(application_matcher, application);
+        }
+        false
+    }
+
+    fn match_window(&mut self, window_matcher: &Application) -> bool {
+        // Lazily fill the window title cache
+        if self.window_cache.is_none() {
+            match self.application_client.current_window() {
+                Some(window) => self.window_cache = Some(window),
+                None => self.window_cache = Some(String::new()),
             }
         }
+
+        if let Some(window) = &self.window_cache {
+            return match_matchers(window_matcher, window);
+        }
         false
     }
 
+    fn match_condition(&mut self, condition: &Condition, device: &InputDeviceInfo) -> bool {
+        match condition {
+            Condition::All(conditions) => conditions.iter().all(|c| self.match_condition(c, device)),
+            Condition::Any(conditions) => conditions.iter().any(|c| self.match_condition(c, device)),
+            Condition::Not(condition) => !self.match_condition(condition, device),
+            Condition::Application(application_matcher) => self.match_application(application_matcher),
+            Condition::Window(window_matcher) => self.match_window(window_matcher),
+            Condition::Mode(modes) => modes.contains(&self.mode),
+            Condition::Device(device_matcher) => device_matcher.matches(device),
+            Condition::Held(keys) => keys
+                .iter()
+                .all(|key| self.pressed_keys.contains_key(key) || self.modifiers.contains(key)),
+        }
+    }
+
+    fn push_layer(&mut self, name: &str, held_by: Option<Key>, oneshot: bool) {
+        self.layers.push(Layer {
+            name: name.to_string(),
+            held_by,
+            oneshot,
+        });
+        self.print_layers();
+    }
+
+    // Deactivate layers held by the key
+    fn release_layers(&mut self, key: &Key) {
+        if self.layers.iter().any(|layer| layer.held_by == Some(*key)) {
+            self.remove_layers(|layer| layer.held_by == Some(*key));
+        }
+    }
+
+    fn remove_layers(&mut self, predicate: impl Fn(&Layer) -> bool) {
+        self.layers.retain(|layer| !predicate(layer));
+        self.print_layers();
+    }
+
+    fn print_layers(&self) {
+        let names: Vec<&str> = self.layers.iter().map(|layer| layer.name.as_str()).collect();
+        println!("layers: {:?}", names);
+    }
+
     fn update_modifier(&mut self, key: Key, value: i32) {
         if value == PRESS {
             self.modifiers.insert(key);
@@ -619,6 +1451,16 @@ impl EventHandler {
     }
 }
 
+fn match_matchers(matcher: &Application, name: &str) -> bool {
+    if let Some(only) = &matcher.only {
+        return only.iter().any(|m| m.matches(name));
+    }
+    if let Some(not) = &matcher.not {
+        return not.iter().all(|m| !m.matches(name));
+    }
+    false
+}
+
 fn is_remap(actions: &Vec<KeymapAction>) -> bool {
     actions.iter().all(|x| match x {
         KeymapAction::Remap(..) => true,
@@ -691,6 +1533,15 @@ fn is_pressed(value: i32) -> bool {
     value == PRESS || value == REPEAT
 }
 
+// Ctrl+Alt+Shift+Esc held for 2 seconds. Either side of each modifier works.
+static EMERGENCY_CHORD: [[Key; 2]; 4] = [
+    [Key::KEY_LEFTCTRL, Key::KEY_RIGHTCTRL],
+    [Key::KEY_LEFTALT, Key::KEY_RIGHTALT],
+    [Key::KEY_LEFTSHIFT, Key::KEY_RIGHTSHIFT],
+    [Key::KEY_ESC, Key::KEY_ESC],
+];
+const EMERGENCY_HOLD: Duration = Duration::from_secs(2);
+
 // InputEvent#value
 static RELEASE: i32 = 0;
 static PRESS: i32 = 1;
@@ -698,6 +1549,35 @@ static REPEAT: i32 = 2;
 
 // ---
 
+struct TapDanceState {
+    tap_dance: TapDance,
+    // The number of presses so far
+    taps: usize,
+    pressed: bool,
+    // Whether `hold` is being held
+    holding: bool,
+    // When the taps are decided unless the key is pressed or released again
+    timeout_at: Instant,
+}
+
+struct Layer {
+    name: String,
+    // { layer_hold: String } deactivates the layer when this key is released
+    held_by: Option<Key>,
+    // { layer_oneshot: String } deactivates the layer after the next key press
+    oneshot: bool,
+}
+
+struct OneShotState {
+    modifier: Key,
+    // Whether the one-shot key is physically held
+    pressed: bool,
+    // Another key was pressed while holding it, so it's released as a normal modifier
+    used: bool,
+    // When the armed modifier is cancelled, if timeout_millis is set
+    timeout_at: Option<Instant>,
+}
+
 #[derive(Debug)]
 struct MultiPurposeKeyState {
     held: Key,
@@ -707,9 +1587,9 @@ struct MultiPurposeKeyState {
 }
 
 impl MultiPurposeKeyState {
-    fn repeat(&mut self) -> Vec<(Key, i32)> {
+    fn repeat(&mut self, now: Instant) -> Vec<(Key, i32)> {
         if let Some(alone_timeout_at) = &self.alone_timeout_at {
-            if Instant::now() < *alone_timeout_at {
+            if now < *alone_timeout_at {
                 vec![] // still delay the press
             } else {
                 self.alone_timeout_at = None; // timeout
@@ -720,9 +1600,9 @@ impl MultiPurposeKeyState {
         }
     }
 
-    fn release(&self) -> Vec<(Key, i32)> {
+    fn release(&self, now: Instant) -> Vec<(Key, i32)> {
         if let Some(alone_timeout_at) = &self.alone_timeout_at {
-            if Instant::now() < *alone_timeout_at {
+            if now < *alone_timeout_at {
                 // dispatch the delayed press and this release
                 vec![(self.alone, PRESS), (self.alone, RELEASE)]
             } else {
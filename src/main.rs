@@ -3,35 +3,55 @@ use crate::device::{device_watcher, get_input_devices, output_device};
 use crate::event_handler::EventHandler;
 use action_dispatcher::ActionDispatcher;
 use anyhow::{anyhow, bail, Context};
-use clap::{AppSettings, ArgEnum, IntoApp, Parser};
+use clap::{AppSettings, ArgEnum, IntoApp, Parser, Subcommand};
 use clap_complete::Shell;
 use client::build_client;
-use config::{config_watcher, load_config};
+use clock::SystemClock;
+use config::{add_config_watches, config_watcher, load_configs};
+use control::{ControlSocket, Request, Response};
 use device::InputDevice;
+use evdev::InputEvent;
 use event::Event;
 use nix::libc::ENODEV;
 use nix::sys::inotify::{AddWatchFlags, Inotify, InotifyEvent};
 use nix::sys::select::select;
 use nix::sys::select::FdSet;
+use nix::sys::signal::{SigSet, Signal};
+use nix::sys::signalfd::{SfdFlags, SignalFd};
 use nix::sys::timerfd::{ClockId, TimerFd, TimerFlags};
+use record::Recorder;
+use serde_json::json;
+use status::StatusPublisher;
 use std::collections::HashMap;
 use std::io::stdout;
 use std::os::unix::io::{AsRawFd, RawFd};
+use std::panic::{self, AssertUnwindSafe};
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::process::exit;
+use std::rc::Rc;
 
 mod action;
 mod action_dispatcher;
+mod action_queue;
 mod client;
+mod clock;
 mod config;
+mod control;
 mod device;
 mod event;
 mod event_handler;
+mod record;
+mod simulate;
+mod status;
 #[cfg(test)]
 mod tests;
 
 #[derive(Parser, Debug)]
-#[clap(version, global_setting(AppSettings::DeriveDisplayOrder))]
+#[clap(
+    version,
+    global_setting(AppSettings::DeriveDisplayOrder),
+    subcommand_negates_reqs = true
+)]
 struct Opts {
     /// Include a device name or path
     #[clap(long, use_delimiter = true)]
@@ -67,9 +87,94 @@ struct Opts {
     /// - in fish: xremap --completions fish | source
     #[clap(long, arg_enum, display_order = 100, value_name = "SHELL", verbatim_doc_comment)]
     completions: Option<Shell>,
-    /// Config file
-    #[clap(required_unless_present = "completions")]
-    config: Option<PathBuf>,
+    /// Listen to `xremap ctl` on a control socket
+    #[clap(long)]
+    control: bool,
+    /// Path of the control socket [default: $XDG_RUNTIME_DIR/xremap.sock]
+    ///
+    /// This also enables the control socket.
+    #[clap(long, value_name = "PATH", global = true)]
+    socket: Option<PathBuf>,
+    /// Keep the mode and other status in a JSON file for status bars
+    #[clap(long, value_name = "PATH")]
+    state_file: Option<PathBuf>,
+    /// Check the config files and exit without opening any device
+    #[clap(long, display_order = 100)]
+    check: bool,
+    /// Print a JSON Schema of the config file for editor completion and validation
+    #[clap(long, display_order = 100)]
+    print_schema: bool,
+    /// Config file(s)
+    ///
+    /// When multiple files are given, modmap and keymap are concatenated in order,
+    /// and other settings are taken from the last file that sets them.
+    #[clap(required_unless_present_any = &["completions", "print-schema"], value_name = "CONFIG", verbatim_doc_comment)]
+    configs: Vec<PathBuf>,
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Control a running xremap started with --control
+    Ctl {
+        #[clap(subcommand)]
+        command: CtlCommand,
+    },
+    /// Remap as usual while writing raw input events to a file, for reproducing a bug with `replay`
+    ///
+    /// Options like --device need to be given before `record`.
+    Record {
+        /// File to write the events to, as JSON lines
+        #[clap(value_name = "OUTPUT")]
+        output: PathBuf,
+        /// Config file(s)
+        #[clap(required = true, value_name = "CONFIG")]
+        configs: Vec<PathBuf>,
+    },
+    /// Print the actions that a config produces for events written by `record`
+    Replay {
+        /// File written by `record`
+        #[clap(value_name = "RECORDING")]
+        recording: PathBuf,
+        /// Config file(s)
+        #[clap(long = "config", required = true, value_name = "CONFIG")]
+        configs: Vec<PathBuf>,
+    },
+    /// Print the actions that a config produces for the given keys, without opening any device
+    Simulate {
+        /// Config file(s)
+        #[clap(required = true, value_name = "CONFIG")]
+        configs: Vec<PathBuf>,
+        /// Application seen by `application` in the config
+        #[clap(long, value_name = "APPLICATION")]
+        app: Option<String>,
+        /// Keys to type, e.g. "C-x C-s, capslock:hold 300ms, a"
+        ///
+        /// Steps are separated by spaces or commas. KEY taps a key with modifiers like C-x,
+        /// KEY:press and KEY:release send one of them, KEY:hold 300ms holds a key,
+        /// and 300ms or 1s waits.
+        #[clap(long, value_name = "KEYS", verbatim_doc_comment)]
+        keys: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CtlCommand {
+    /// Print the current mode, or set it if it's given
+    Mode { mode: Option<String> },
+    /// Ungrab all devices and stop remapping
+    Pause,
+    /// Grab devices again and resume remapping
+    Resume,
+    /// Reload the config files
+    Reload,
+    /// List grabbed devices
+    Devices,
+    /// Dump the remapping state, like pressed keys and modifiers
+    State,
+    /// Print a line of JSON on every change of the mode and other status
+    Subscribe,
 }
 
 #[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
@@ -94,20 +199,45 @@ fn main() -> anyhow::Result<()> {
         ignore: ignore_filter,
         mouse,
         watch,
-        config,
+        configs: config_paths,
         completions,
+        check,
+        print_schema,
+        control,
+        socket,
+        state_file,
+        command,
     } = Opts::parse();
 
     if let Some(shell) = completions {
         clap_complete::generate(shell, &mut Opts::into_app(), "xremap", &mut stdout());
         return Ok(());
     }
+    let (config_paths, record) = match command {
+        Some(Command::Ctl { command }) => {
+            return run_ctl(&socket.unwrap_or_else(control::default_socket_path), command);
+        }
+        Some(Command::Replay { recording, configs }) => return record::replay(&recording, &configs),
+        Some(Command::Simulate { configs, app, keys }) => return simulate::simulate(&configs, app, &keys),
+        Some(Command::Record { output, configs }) => (configs, Some(output)),
+        None => (config_paths, None),
+    };
+    if print_schema {
+        println!("{}", serde_json::to_string_pretty(&config::schema::config_schema())?);
+        return Ok(());
+    }
+    if check {
+        if config::check::check_configs(&config_paths) {
+            return Ok(());
+        } else {
+            exit(1);
+        }
+    }
 
     // Configuration
-    let config_path = config.expect("config is set, if not completions");
-    let mut config = match config::load_config(&config_path) {
+    let mut config = match config::load_configs(&config_paths) {
         Ok(config) => config,
-        Err(e) => bail!("Failed to load config '{}': {}", config_path.display(), e),
+        Err(e) => bail!("Failed to load config: {}", e),
     };
     let watch_devices = watch.contains(&WatchTargets::Device);
     let watch_config = watch.contains(&WatchTargets::Config);
@@ -115,95 +245,220 @@ fn main() -> anyhow::Result<()> {
     // Event listeners
     let timer = TimerFd::new(ClockId::CLOCK_MONOTONIC, TimerFlags::empty())?;
     let timer_fd = timer.as_raw_fd();
-    let delay = Duration::from_millis(config.keypress_delay_ms);
+    // Resumes actions after keypress_delay_ms without blocking the event loop
+    let delay_timer = TimerFd::new(ClockId::CLOCK_MONOTONIC, TimerFlags::empty())?;
+    let delay_timer_fd = delay_timer.as_raw_fd();
+    let mut signal_fd = signal_fd().context("Setting up signal handlers")?;
+    let delay = config.keypress_delay();
     let mut input_devices = match get_input_devices(&device_filter, &ignore_filter, mouse, watch_devices) {
         Ok(input_devices) => input_devices,
         Err(e) => bail!("Failed to prepare input devices: {}", e),
     };
     let device_watcher = device_watcher(watch_devices).context("Setting up device watcher")?;
-    let config_watcher = config_watcher(watch_config, &config_path).context("Setting up config watcher")?;
+    let config_watcher = config_watcher(watch_config, &config).context("Setting up config watcher")?;
     let watchers: Vec<_> = device_watcher.iter().chain(config_watcher.iter()).collect();
-    let mut handler = EventHandler::new(timer, &config.default_mode, delay, build_client());
+    let mut handler =
+        EventHandler::new(Box::new(timer), Box::new(SystemClock), config.default_mode(), delay, build_client());
     let output_device = match output_device(input_devices.values().next().map(InputDevice::bus_type)) {
         Ok(output_device) => output_device,
         Err(e) => bail!("Failed to prepare an output device: {}", e),
     };
-    let mut dispatcher = ActionDispatcher::new(output_device);
+    let mut dispatcher = ActionDispatcher::new(output_device, Box::new(delay_timer), Box::new(SystemClock));
+    let control = match socket {
+        Some(path) => Some(ControlSocket::bind(&path)?),
+        None if control => Some(ControlSocket::bind(&control::default_socket_path())?),
+        None => None,
+    };
+    // Devices are ungrabbed and events are not remapped while paused by `xremap ctl pause`
+    let mut paused = false;
+    let mut publisher = StatusPublisher::new(state_file);
+    let mut recorder = match record {
+        Some(path) => Some(Recorder::create(&path)?),
+        None => None,
+    };
 
-    // Main loop
-    loop {
-        match 'event_loop: loop {
-            let readable_fds = select_readable(input_devices.values(), &watchers, timer_fd)?;
-            if readable_fds.contains(timer_fd) {
-                if let Err(error) =
-                    handle_events(&mut handler, &mut dispatcher, &mut config, vec![Event::OverrideTimeout])
-                {
-                    println!("Error on remap timeout: {error}")
+    // Main loop. Keys pressed on the output device are released however it ends, even on a panic.
+    let result = panic::catch_unwind(AssertUnwindSafe(|| -> anyhow::Result<()> {
+        loop {
+            match 'event_loop: loop {
+                if handler.take_emergency() {
+                    // Don't let the releases wait for delayed actions
+                    dispatcher.flush()?;
+                    for input_device in input_devices.values_mut() {
+                        input_device.ungrab();
+                    }
+                    // Without the control socket, there's no way to resume
+                    if control.is_none() {
+                        println!("Exiting on the emergency chord.");
+                        return Ok(());
+                    }
+                    println!("Paused on the emergency chord. Run `xremap ctl resume` to resume.");
+                    paused = true;
                 }
-            }
-
-            for input_device in input_devices.values_mut() {
-                if !readable_fds.contains(input_device.as_raw_fd()) {
-                    continue;
+                // Publish changes made by the last events before waiting for the next ones
+                publisher.publish(handler.status(paused));
+                let readable_fds = select_readable(
+                    input_devices.values(),
+                    &watchers,
+                    timer_fd,
+                    delay_timer_fd,
+                    signal_fd.as_raw_fd(),
+                    control.as_ref(),
+                )?;
+                if readable_fds.contains(signal_fd.as_raw_fd()) {
+                    if let Ok(Some(signal)) = signal_fd.read_signal() {
+                        println!("Exiting on signal {}", signal.ssi_signo);
+                        return Ok(());
+                    }
+                }
+                if readable_fds.contains(delay_timer_fd) {
+                    if let Err(error) = dispatcher.on_timeout() {
+                        println!("Error on delayed actions: {error}")
+                    }
+                }
+                if readable_fds.contains(timer_fd) {
+                    if let Err(error) =
+                        handle_events(&mut handler, &mut dispatcher, &mut config, vec![Event::OverrideTimeout])
+                    {
+                        println!("Error on remap timeout: {error}")
+                    }
                 }
 
-                if !handle_input_events(input_device, &mut handler, &mut dispatcher, &mut config)? {
-                    println!("Found a removed device. Reselecting devices.");
-                    break 'event_loop ReloadEvent::ReloadDevices;
+                for input_device in input_devices.values_mut() {
+                    if !readable_fds.contains(input_device.as_raw_fd()) {
+                        continue;
+                    }
+                    if paused {
+                        // The events are already passed to the system since the device is ungrabbed
+                        let _ = input_device.fetch_events();
+                        continue;
+                    }
+
+                    if !handle_input_events(
+                        input_device,
+                        &mut handler,
+                        &mut dispatcher,
+                        &mut config,
+                        recorder.as_mut(),
+                    )? {
+                        println!("Found a removed device. Reselecting devices.");
+                        break 'event_loop ReloadEvent::ReloadDevices;
+                    }
                 }
-            }
 
-            if let Some(inotify) = device_watcher {
-                if let Ok(events) = inotify.read_events() {
-                    handle_device_changes(events, &mut input_devices, &device_filter, &ignore_filter, mouse)?;
+                if let Some(inotify) = device_watcher {
+                    if let Ok(events) = inotify.read_events() {
+                        // New devices are found when they're reselected on resume
+                        if !paused {
+                            handle_device_changes(events, &mut input_devices, &device_filter, &ignore_filter, mouse)?;
+                        }
+                    }
                 }
-            }
-            if let Some(inotify) = config_watcher {
-                if let Ok(events) = inotify.read_events() {
-                    if !handle_config_changes(
-                        events,
-                        &mut input_devices,
-                        &device_filter,
-                        &ignore_filter,
-                        mouse,
-                        &config_path,
-                    )? {
-                        break 'event_loop ReloadEvent::ReloadConfig;
+                if let Some(control) = &control {
+                    if readable_fds.contains(control.as_raw_fd()) {
+                        let was_paused = paused;
+                        let subscribers = control.handle_requests(|request| match request {
+                            Request::Reload => {
+                                match reload_config(
+                                    &mut config,
+                                    &config_paths,
+                                    config_watcher,
+                                    &mut handler,
+                                    &mut dispatcher,
+                                ) {
+                                    Ok(changes) => Response::ok(json!(changes)),
+                                    Err(e) => Response::error(e),
+                                }
+                            }
+                            request => {
+                                // Keys pressed on the ungrabbed devices would be stuck
+                                if matches!(request, Request::Pause) {
+                                    if let Err(e) = release_keys(&mut handler, &mut dispatcher) {
+                                        println!("Failed to release keys: {e}");
+                                    }
+                                }
+                                handle_control_request(request, &mut handler, &mut input_devices, &mut paused)
+                            }
+                        });
+                        for subscriber in subscribers {
+                            publisher.subscribe(subscriber);
+                        }
+                        if was_paused && !paused {
+                            break 'event_loop ReloadEvent::ReloadDevices;
+                        }
                     }
                 }
-            }
-        } {
-            ReloadEvent::ReloadDevices => {
-                for input_device in input_devices.values_mut() {
-                    input_device.ungrab();
+                if let Some(inotify) = config_watcher {
+                    if let Ok(events) = inotify.read_events() {
+                        if !handle_config_changes(
+                            events,
+                            &mut input_devices,
+                            &device_filter,
+                            &ignore_filter,
+                            mouse,
+                            &config,
+                        )? {
+                            break 'event_loop ReloadEvent::ReloadConfig;
+                        }
+                    }
                 }
-                input_devices = match get_input_devices(&device_filter, &ignore_filter, mouse, watch_devices) {
-                    Ok(input_devices) => input_devices,
-                    Err(e) => bail!("Failed to prepare input devices: {}", e),
-                };
-            }
-            ReloadEvent::ReloadConfig => {
-                match (config.modify_time, config_path.metadata().and_then(|m| m.modified())) {
-                    (Some(last_mtime), Ok(current_mtim)) if last_mtime == current_mtim => continue,
+            } {
+                ReloadEvent::ReloadDevices => {
+                    release_keys(&mut handler, &mut dispatcher)?;
+                    for input_device in input_devices.values_mut() {
+                        input_device.ungrab();
+                    }
+                    input_devices = match get_input_devices(&device_filter, &ignore_filter, mouse, watch_devices) {
+                        Ok(input_devices) => input_devices,
+                        Err(e) => bail!("Failed to prepare input devices: {}", e),
+                    };
+                }
+                ReloadEvent::ReloadConfig => match (config.modify_time, config::modify_time(&config.watched_paths())) {
+                    (Some(last_mtime), Some(current_mtim)) if last_mtime == current_mtim => continue,
                     _ => {
-                        if let Ok(c) = load_config(&config_path) {
-                            println!("Reloading Config");
-                            config = c;
+                        if let Err(e) =
+                            reload_config(&mut config, &config_paths, config_watcher, &mut handler, &mut dispatcher)
+                        {
+                            println!("{e}");
                         }
                     }
-                }
+                },
             }
         }
+    }));
+    release_keys(&mut handler, &mut dispatcher)?;
+    dispatcher.flush()?;
+    match result {
+        Ok(result) => result,
+        Err(panic) => panic::resume_unwind(panic),
     }
 }
 
+// Receive termination signals through select(2), so that keys are released before exiting
+fn signal_fd() -> anyhow::Result<SignalFd> {
+    let mut mask = SigSet::empty();
+    mask.add(Signal::SIGINT);
+    mask.add(Signal::SIGTERM);
+    mask.add(Signal::SIGHUP);
+    mask.thread_block()?;
+    Ok(SignalFd::with_flags(&mask, SfdFlags::SFD_NONBLOCK)?)
+}
+
 fn select_readable<'a>(
     devices: impl Iterator<Item = &'a InputDevice>,
     watchers: &[&Inotify],
     timer_fd: RawFd,
+    delay_timer_fd: RawFd,
+    signal_fd: RawFd,
+    control: Option<&ControlSocket>,
 ) -> anyhow::Result<FdSet> {
     let mut read_fds = FdSet::new();
     read_fds.insert(timer_fd);
+    read_fds.insert(delay_timer_fd);
+    read_fds.insert(signal_fd);
+    if let Some(control) = control {
+        read_fds.insert(control.as_raw_fd());
+    }
     for device in devices {
         read_fds.insert(device.as_raw_fd());
     }
@@ -220,23 +475,38 @@ fn handle_input_events(
     handler: &mut EventHandler,
     dispatcher: &mut ActionDispatcher,
     config: &mut Config,
+    recorder: Option<&mut Recorder>,
 ) -> anyhow::Result<bool> {
+    let device = Rc::new(input_device.to_info());
     match input_device.fetch_events().map_err(|e| (e.raw_os_error(), e)) {
         Err((Some(ENODEV), _)) => Ok(false),
         Err((_, error)) => Err(error).context("Error fetching input events"),
         Ok(events) => {
+            let events: Vec<InputEvent> = events.collect();
             let mut input_events: Vec<Event> = Vec::new();
-            for event in events {
-                let event = Event::new(event);
+            for event in &events {
+                let event = Event::new(Rc::clone(&device), *event);
                 input_events.push(event);
             }
             handle_events(handler, dispatcher, config, input_events)?;
+            // Record after handling to know the application it was handled for
+            if let Some(recorder) = recorder {
+                recorder.record(&device, &events, handler.status(false).application)?;
+            }
 
             Ok(true)
         }
     }
 }
 
+// Release all keys pressed on the output device
+fn release_keys(handler: &mut EventHandler, dispatcher: &mut ActionDispatcher) -> anyhow::Result<()> {
+    for action in handler.release_all() {
+        dispatcher.on_action(action)?;
+    }
+    Ok(())
+}
+
 // Handle an Event with EventHandler, and dispatch Actions with ActionDispatcher
 fn handle_events(
     handler: &mut EventHandler,
@@ -253,6 +523,98 @@ fn handle_events(
     Ok(())
 }
 
+// Swap the config only if the new one loads, and carry the runtime state over to it.
+// Returns a summary of the changes.
+fn reload_config(
+    config: &mut Config,
+    config_paths: &[PathBuf],
+    config_watcher: Option<Inotify>,
+    handler: &mut EventHandler,
+    dispatcher: &mut ActionDispatcher,
+) -> anyhow::Result<Vec<String>> {
+    let new_config = match load_configs(config_paths) {
+        Ok(new_config) => new_config,
+        Err(e) => bail!("Failed to reload config, keeping the current one: {}", e),
+    };
+    println!("Reloading Config");
+    let changes = config::diff::config_changes(config, &new_config);
+    for change in &changes {
+        println!("  {}", change);
+    }
+    // Held keys and pending nested remaps, combos, etc. belong to the old config
+    release_keys(handler, dispatcher)?;
+    *config = new_config;
+    handler.on_config_reload(config);
+    if let Some(inotify) = config_watcher {
+        add_config_watches(&inotify, config)?;
+    }
+    Ok(changes)
+}
+
+// Reply to a request from `xremap ctl`
+fn handle_control_request(
+    request: Request,
+    handler: &mut EventHandler,
+    input_devices: &mut HashMap<PathBuf, InputDevice>,
+    paused: &mut bool,
+) -> Response {
+    match request {
+        Request::GetMode => Response::ok(json!(handler.mode())),
+        Request::SetMode { mode } => {
+            handler.set_mode(&mode);
+            Response::ok(json!(mode))
+        }
+        Request::Pause => {
+            if !*paused {
+                for input_device in input_devices.values_mut() {
+                    input_device.ungrab();
+                }
+                *paused = true;
+                println!("paused");
+            }
+            Response::ok(json!(null))
+        }
+        Request::Resume => {
+            // Devices are grabbed again after this returns
+            if *paused {
+                *paused = false;
+                println!("resumed");
+            }
+            Response::ok(json!(null))
+        }
+        Request::Devices => {
+            let mut devices: Vec<_> = input_devices.values().map(InputDevice::to_info).collect();
+            devices.sort_by(|a, b| a.path.cmp(&b.path));
+            Response::ok(json!({ "paused": *paused, "devices": devices }))
+        }
+        Request::State => Response::ok(handler.state()),
+        // Handled by the caller, or by ControlSocket keeping the connection for the publisher
+        Request::Reload | Request::Subscribe => Response::error("unexpected request"),
+    }
+}
+
+// `xremap ctl`: send a request to the control socket and print the result
+fn run_ctl(socket: &Path, command: CtlCommand) -> anyhow::Result<()> {
+    let request = match command {
+        CtlCommand::Mode { mode: None } => Request::GetMode,
+        CtlCommand::Mode { mode: Some(mode) } => Request::SetMode { mode },
+        CtlCommand::Pause => Request::Pause,
+        CtlCommand::Resume => Request::Resume,
+        CtlCommand::Reload => Request::Reload,
+        CtlCommand::Devices => Request::Devices,
+        CtlCommand::State => Request::State,
+        CtlCommand::Subscribe => return control::subscribe(socket),
+    };
+    let response = control::send_request(socket, &request)?;
+    match (response.ok, response.result) {
+        (true, Some(serde_json::Value::String(result))) => println!("{}", result),
+        (true, Some(serde_json::Value::Null) | None) => {}
+        (true, Some(result)) => println!("{}", serde_json::to_string_pretty(&result)?),
+        (false, _) => bail!("{}", response.error.unwrap_or_default()),
+    }
+    Ok(())
+}
+
 fn handle_device_changes(
     events: Vec<InotifyEvent>,
     input_devices: &mut HashMap<PathBuf, InputDevice>,
@@ -281,12 +643,18 @@ fn handle_config_changes(
     device_filter: &[String],
     ignore_filter: &[String],
     mouse: bool,
-    config_path: &Path,
+    config: &Config,
 ) -> anyhow::Result<bool> {
     for event in &events {
         match (event.mask, &event.name) {
             // Dir events
-            (_, Some(name)) if name == config_path.file_name().expect("Config path has a file name") => {
+            (_, Some(name))
+                if config
+                    .config_files
+                    .iter()
+                    .any(|file| name == file.file_name().expect("Config path has a file name"))
+                    || config.matches_include_glob(name) =>
+            {
                 return Ok(false)
             }
             // File events
@@ -0,0 +1,166 @@
+use crate::action::Action;
+use crate::config::{load_configs, Config};
+use crate::device::InputDeviceInfo;
+use crate::event::Event;
+use crate::simulate::{print_actions, Simulator};
+use anyhow::{anyhow, Context};
+use evdev::{EventType, InputEvent};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions, Permissions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+// A line of a recording: events fetched from a device at once, before any remapping, e.g.
+// `{"time_us":1500,"device":{"name":"...","path":"/dev/input/event3"},"application":"firefox","events":[[1,30,1]]}`
+#[derive(Deserialize, Serialize)]
+struct Record {
+    // Monotonic time since the recording started
+    time_us: u64,
+    device: InputDeviceInfo,
+    // The application seen when the events were handled
+    application: Option<String>,
+    // [type, code, value] of InputEvents
+    events: Vec<(u16, u16, i32)>,
+}
+
+// `xremap record`: write raw input events to a file while remapping them as usual
+pub struct Recorder {
+    writer: BufWriter<File>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> anyhow::Result<Recorder> {
+        // The recording has every keystroke, so keep it readable only by the owner
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)
+            .with_context(|| format!("creating {}", path.display()))?;
+        // mode() only applies to a new file
+        file.set_permissions(Permissions::from_mode(0o600))?;
+        println!("recording: {}", path.display());
+        Ok(Recorder {
+            writer: BufWriter::new(file),
+            start: Instant::now(),
+        })
+    }
+
+    pub fn record(
+        &mut self,
+        device: &InputDeviceInfo,
+        events: &[InputEvent],
+        application: Option<String>,
+    ) -> anyhow::Result<()> {
+        let record = Record {
+            time_us: self.start.elapsed().as_micros() as u64,
+            device: device.clone(),
+            application,
+            events: events
+                .iter()
+                .map(|event| (event.event_type().0, event.code(), event.value()))
+                .collect(),
+        };
+        writeln!(self.writer, "{}", serde_json::to_string(&record)?)?;
+        // Keep the file complete even if xremap is killed
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+// `xremap replay`: feed a recording to EventHandler on the recorded timeline and print the Actions.
+// This needs neither uinput nor access to input devices.
+pub fn replay(path: &Path, config_paths: &[PathBuf]) -> anyhow::Result<()> {
+    let config = load_configs(config_paths).map_err(|e| anyhow!("Failed to load config: {}", e))?;
+    print_actions(&replay_file(path, &config)?);
+    Ok(())
+}
+
+fn replay_file(path: &Path, config: &Config) -> anyhow::Result<Vec<(Duration, Action)>> {
+    let mut simulator = Simulator::new(config)?;
+    let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let record: Record =
+            serde_json::from_str(&line?).with_context(|| format!("parsing {}:{}", path.display(), index + 1))?;
+        simulator.advance(Duration::from_micros(record.time_us))?;
+        simulator.set_application(record.application);
+        let device = Rc::new(record.device);
+        let events: Vec<Event> = record
+            .events
+            .iter()
+            .map(|(event_type, code, value)| {
+                Event::new(Rc::clone(&device), InputEvent::new(EventType(*event_type), *code, *value))
+            })
+            .collect();
+        simulator.send(events)?;
+    }
+    simulator.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::keymap::build_keymap_table;
+    use indoc::indoc;
+    use std::fs;
+
+    #[test]
+    fn test_replay() {
+        let mut config: Config = serde_yaml::from_str(indoc! {"
+            keymap:
+              - application:
+                  only: firefox
+                remap:
+                  C-b: left
+              - remap:
+                  C-x:
+                    remap:
+                      C-s: C-w
+                    timeout_millis: 1000
+        "})
+        .unwrap();
+        config.keymap_table = build_keymap_table(&config.keymap);
+        let path = std::env::temp_dir().join(format!("xremap-test-{}.jsonl", std::process::id()));
+        let device = r#""device":{"name":"kbd","path":"/dev/input/event3"}"#;
+        let records = [
+            (0, "firefox", "[1,29,1]"),
+            (100_000, "firefox", "[1,48,1]"),
+            (200_000, "kitty", "[1,48,0]"),
+            (300_000, "kitty", "[1,45,1]"),
+        ];
+        let lines: Vec<String> = records
+            .iter()
+            .map(|(time_us, application, event)| {
+                format!(r#"{{"time_us":{},{},"application":"{}","events":[{}]}}"#, time_us, device, application, event)
+            })
+            .collect();
+        fs::write(&path, lines.join("\n")).unwrap();
+        let actions = replay_file(&path, &config).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let actions: Vec<String> = actions
+            .iter()
+            .map(|(time, action)| format!("{} {:?}", time.as_millis(), action))
+            .collect();
+        assert_eq!(
+            vec![
+                "0 KeyEvent(KeyEvent { key: KEY_LEFTCTRL, value: Press })",
+                "100 KeyEvent(KeyEvent { key: KEY_LEFTCTRL, value: Release })",
+                "100 KeyEvent(KeyEvent { key: KEY_LEFT, value: Press })",
+                "100 KeyEvent(KeyEvent { key: KEY_LEFT, value: Release })",
+                "100 Delay(0ns)",
+                "100 KeyEvent(KeyEvent { key: KEY_LEFTCTRL, value: Press })",
+                "200 KeyEvent(KeyEvent { key: KEY_B, value: Release })",
+                // The nested remap times out on the recorded timeline
+                "1300 KeyEvent(KeyEvent { key: KEY_X, value: Press })",
+                "1300 KeyEvent(KeyEvent { key: KEY_X, value: Release })",
+            ],
+            actions
+        );
+    }
+}
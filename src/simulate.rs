@@ -0,0 +1,405 @@
+use crate::action::Action;
+use crate::action_queue::ActionQueue;
+use crate::client::{Client, WMClient};
+use crate::clock::{Clock, ManualClock, ManualTimer};
+use crate::config::key_press::{parse_key_press, KeyPress, Modifier};
+use crate::config::{load_configs, Config};
+use crate::device::InputDeviceInfo;
+use crate::event::{Event, KeyEvent, KeyValue};
+use crate::event_handler::EventHandler;
+use anyhow::{anyhow, bail};
+use evdev::Key;
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+
+// Run EventHandler on a timeline driven by the caller instead of the real clock and devices.
+// Used by `xremap simulate`, `xremap replay`, and tests.
+pub struct Simulator<'a> {
+    config: &'a Config,
+    handler: EventHandler,
+    clock: ManualClock,
+    timer: ManualTimer,
+    // Actions waiting for a delay as in ActionDispatcher
+    queue: ActionQueue,
+    focus: Rc<RefCell<Focus>>,
+    // Actions with the time they're emitted at
+    actions: Vec<(Duration, Action)>,
+}
+
+impl<'a> Simulator<'a> {
+    pub fn new(config: &'a Config) -> anyhow::Result<Simulator<'a>> {
+        let focus = Rc::new(RefCell::new(Focus::default()));
+        let client = ScriptedClient { focus: focus.clone() };
+        let clock = ManualClock::new();
+        // Timeouts are fired by advance()
+        let timer = ManualTimer::new();
+        let handler = EventHandler::new(
+            Box::new(timer.clone()),
+            Box::new(clock.clone()),
+            config.default_mode(),
+            config.keypress_delay(),
+            WMClient::new("simulate", Box::new(client)),
+        );
+        Ok(Simulator {
+            config,
+            handler,
+            clock,
+            timer,
+            queue: ActionQueue::default(),
+            focus,
+            actions: vec![],
+        })
+    }
+
+    // The application that `application` conditions see from now on
+    pub fn set_application(&mut self, application: Option<String>) {
+        self.focus.borrow_mut().application = application;
+    }
+
+    // The window title that `window` conditions see from now on
+    #[cfg(test)]
+    pub fn set_window(&mut self, window: Option<String>) {
+        self.focus.borrow_mut().window = window;
+    }
+
+    // For tests that drive EventHandler directly with the clock and the timer of the simulation
+    #[cfg(test)]
+    pub fn handler(&mut self) -> &mut EventHandler {
+        &mut self.handler
+    }
+
+    #[cfg(test)]
+    pub fn clock(&self) -> &ManualClock {
+        &self.clock
+    }
+
+    #[cfg(test)]
+    pub fn timer(&self) -> &ManualTimer {
+        &self.timer
+    }
+
+    // Move the clock forward to `elapsed`, firing the timeouts on the way
+    pub fn advance(&mut self, elapsed: Duration) -> anyhow::Result<()> {
+        self.fire_timeouts(Some(elapsed))?;
+        if elapsed > self.clock.elapsed() {
+            self.clock.set_elapsed(elapsed);
+        }
+        Ok(())
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.clock.elapsed()
+    }
+
+    pub fn send(&mut self, events: Vec<Event>) -> anyhow::Result<()> {
+        let actions = self
+            .handler
+            .on_events(&events, self.config)
+            .map_err(|e| anyhow!("{}", e))?;
+        for action in actions {
+            self.queue.push(action);
+        }
+        self.dispatch_due_actions();
+        Ok(())
+    }
+
+    // Let whatever is still pending time out, and return all Actions
+    pub fn finish(mut self) -> anyhow::Result<Vec<(Duration, Action)>> {
+        self.fire_timeouts(None)?;
+        Ok(self.actions)
+    }
+
+    // Return the Actions emitted so far, including the ones waiting for a delay, without firing timeouts
+    #[cfg(test)]
+    pub fn take_actions(&mut self) -> Vec<Action> {
+        let mut actions: Vec<Action> = self.actions.drain(..).map(|(_, action)| action).collect();
+        actions.extend(self.queue.drain());
+        actions
+    }
+
+    fn dispatch_due_actions(&mut self) {
+        let time = self.clock.elapsed();
+        for action in self.queue.pop_due(self.clock.now()) {
+            self.actions.push((time, action));
+        }
+    }
+
+    // Fire timeouts of EventHandler and resume delayed actions in time order
+    fn fire_timeouts(&mut self, until: Option<Duration>) -> anyhow::Result<()> {
+        let mut stuck_timeout = None;
+        loop {
+            let handler_timeout = self
+                .timer
+                .deadline()
+                .filter(|timeout_at| Some(*timeout_at) != stuck_timeout);
+            let timeout_at = match handler_timeout.into_iter().chain(self.queue.deadline()).min() {
+                Some(timeout_at) => timeout_at,
+                None => break,
+            };
+            let time = self.clock.elapsed() + timeout_at.saturating_duration_since(self.clock.now());
+            if until.is_some_and(|until| time > until) {
+                break;
+            }
+            self.clock.set_elapsed(time);
+            if handler_timeout == Some(timeout_at) {
+                self.send(vec![Event::OverrideTimeout])?;
+                // Don't loop forever on a timeout that's left as is
+                if self.timer.deadline() == Some(timeout_at) {
+                    stuck_timeout = Some(timeout_at);
+                }
+            } else {
+                self.dispatch_due_actions();
+            }
+        }
+        Ok(())
+    }
+}
+
+// What the window manager would report
+#[derive(Default)]
+struct Focus {
+    application: Option<String>,
+    window: Option<String>,
+}
+
+// Returns the application and the window set by the simulation instead of asking the window manager
+struct ScriptedClient {
+    focus: Rc<RefCell<Focus>>,
+}
+
+impl Client for ScriptedClient {
+    fn supported(&mut self) -> bool {
+        true
+    }
+
+    fn current_application(&mut self) -> Option<String> {
+        self.focus.borrow().application.clone()
+    }
+
+    fn current_window(&mut self) -> Option<String> {
+        self.focus.borrow().window.clone()
+    }
+}
+
+pub fn print_actions(actions: &[(Duration, Action)]) {
+    for (time, action) in actions {
+        println!("{:>10.3}ms {:?}", time.as_secs_f64() * 1000.0, action);
+    }
+}
+
+// `xremap simulate`: print the Actions a config produces for keys given like `C-x C-s, capslock:hold 300ms, a`
+pub fn simulate(config_paths: &[PathBuf], application: Option<String>, keys: &str) -> anyhow::Result<()> {
+    let steps = parse_steps(keys)?;
+    let config = load_configs(config_paths).map_err(|e| anyhow!("Failed to load config: {}", e))?;
+    print_actions(&simulate_steps(&config, application, &steps)?);
+    Ok(())
+}
+
+fn simulate_steps(
+    config: &Config,
+    application: Option<String>,
+    steps: &[Step],
+) -> anyhow::Result<Vec<(Duration, Action)>> {
+    let device = Rc::new(InputDeviceInfo {
+        name: "xremap simulate".to_string(),
+        path: PathBuf::from("/dev/input/simulate"),
+    });
+    let key_event = |key: Key, value: KeyValue| Event::KeyEvent(Rc::clone(&device), KeyEvent::new(key, value));
+
+    let mut simulator = Simulator::new(config)?;
+    simulator.set_application(application);
+    for step in steps {
+        match step {
+            Step::Tap(key_press) => {
+                let modifiers: Vec<Key> = key_press.modifiers.iter().map(modifier_key).collect();
+                let mut events: Vec<Event> = modifiers.iter().map(|key| key_event(*key, KeyValue::Press)).collect();
+                events.push(key_event(key_press.key, KeyValue::Press));
+                events.push(key_event(key_press.key, KeyValue::Release));
+                events.extend(modifiers.iter().rev().map(|key| key_event(*key, KeyValue::Release)));
+                simulator.send(events)?;
+            }
+            Step::Press(key) => simulator.send(vec![key_event(*key, KeyValue::Press)])?,
+            Step::Release(key) => simulator.send(vec![key_event(*key, KeyValue::Release)])?,
+            Step::Wait(duration) => simulator.advance(simulator.elapsed() + *duration)?,
+        }
+    }
+    simulator.finish()
+}
+
+#[derive(Debug, PartialEq)]
+enum Step {
+    // Press modifiers and the key, and release them in reverse
+    Tap(KeyPress),
+    Press(Key),
+    Release(Key),
+    Wait(Duration),
+}
+
+// Steps are separated by spaces or commas: `C-x` taps keys, `KEY:press` and `KEY:release` send one of them,
+// `KEY:hold 300ms` holds a key for the duration, and `300ms` or `1s` waits.
+fn parse_steps(input: &str) -> anyhow::Result<Vec<Step>> {
+    let mut steps = vec![];
+    let mut tokens = input
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|token| !token.is_empty());
+    while let Some(token) = tokens.next() {
+        if let Some(duration) = parse_duration(token)? {
+            steps.push(Step::Wait(duration));
+            continue;
+        }
+        let (name, kind) = token.rsplit_once(':').unwrap_or((token, "tap"));
+        let key_press = parse_key_press(name).map_err(|e| anyhow!("{}: {}", token, e))?;
+        match kind {
+            "tap" => steps.push(Step::Tap(key_press)),
+            "press" | "release" | "hold" if !key_press.modifiers.is_empty() => {
+                bail!("{}: modifiers can't be used with :{}", token, kind)
+            }
+            "press" => steps.push(Step::Press(key_press.key)),
+            "release" => steps.push(Step::Release(key_press.key)),
+            "hold" => match tokens.next().map(parse_duration).transpose()?.flatten() {
+                Some(duration) => {
+                    steps.push(Step::Press(key_press.key));
+                    steps.push(Step::Wait(duration));
+                    steps.push(Step::Release(key_press.key));
+                }
+                None => bail!("{}: expected a duration like 300ms after it", token),
+            },
+            _ => bail!("{}: expected :press, :release, or :hold", token),
+        }
+    }
+    Ok(steps)
+}
+
+// None if the token doesn't look like a duration
+fn parse_duration(token: &str) -> anyhow::Result<Option<Duration>> {
+    let (number, unit) = if let Some(millis) = token.strip_suffix("ms") {
+        (millis, 0.001)
+    } else if let Some(secs) = token.strip_suffix('s') {
+        (secs, 1.0)
+    } else {
+        return Ok(None);
+    };
+    let number: f64 = match number.parse() {
+        Ok(number) => number,
+        Err(_) => return Ok(None),
+    };
+    match Duration::try_from_secs_f64(number * unit) {
+        Ok(duration) => Ok(Some(duration)),
+        Err(_) => bail!("{}: expected a non-negative duration", token),
+    }
+}
+
+// The physical key pressed for a modifier of a tap
+fn modifier_key(modifier: &Modifier) -> Key {
+    match modifier {
+        Modifier::Shift => Key::KEY_LEFTSHIFT,
+        Modifier::Control => Key::KEY_LEFTCTRL,
+        Modifier::Alt => Key::KEY_LEFTALT,
+        Modifier::Windows => Key::KEY_LEFTMETA,
+        Modifier::Key(key) => *key,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::keymap::build_keymap_table;
+    use indoc::indoc;
+
+    #[test]
+    fn test_parse_steps() {
+        assert_eq!(
+            vec![
+                Step::Tap(parse_key_press("C-x").unwrap()),
+                Step::Press(Key::KEY_CAPSLOCK),
+                Step::Wait(Duration::from_millis(300)),
+                Step::Release(Key::KEY_CAPSLOCK),
+                Step::Release(Key::KEY_A),
+                Step::Wait(Duration::from_secs(1)),
+            ],
+            parse_steps("C-x, capslock:hold 300ms a:release 1s").unwrap()
+        );
+        assert!(parse_steps("capslock:hold a").is_err());
+        assert!(parse_steps("C-a:press").is_err());
+        assert!(parse_steps("a:tap2").is_err());
+        assert!(parse_steps("-1s").is_err());
+        assert!(parse_steps("infs").is_err());
+        assert!(parse_steps("a:hold NaNms").is_err());
+    }
+
+    #[test]
+    fn test_simulate_steps() {
+        let mut config: Config = serde_yaml::from_str(indoc! {"
+            modmap:
+              - remap:
+                  CapsLock:
+                    held: Control_L
+                    alone: Esc
+                    alone_timeout_millis: 200
+            keymap:
+              - application:
+                  only: firefox
+                remap:
+                  C-s: C-w
+        "})
+        .unwrap();
+        config.keymap_table = build_keymap_table(&config.keymap);
+        let steps = parse_steps("capslock:hold 100ms, capslock:press 300ms s capslock:release").unwrap();
+        let actions: Vec<String> = simulate_steps(&config, Some("firefox".into()), &steps)
+            .unwrap()
+            .iter()
+            .map(|(time, action)| format!("{} {:?}", time.as_millis(), action))
+            .collect();
+        assert_eq!(
+            vec![
+                "100 KeyEvent(KeyEvent { key: KEY_ESC, value: Press })",
+                "100 KeyEvent(KeyEvent { key: KEY_ESC, value: Release })",
+                "400 KeyEvent(KeyEvent { key: KEY_LEFTCTRL, value: Press })",
+                "400 KeyEvent(KeyEvent { key: KEY_W, value: Press })",
+                "400 KeyEvent(KeyEvent { key: KEY_W, value: Release })",
+                "400 Delay(0ns)",
+                "400 KeyEvent(KeyEvent { key: KEY_S, value: Release })",
+                "400 KeyEvent(KeyEvent { key: KEY_LEFTCTRL, value: Release })",
+            ],
+            actions
+        );
+    }
+
+    #[test]
+    fn test_keypress_delay() {
+        let mut config: Config = serde_yaml::from_str(indoc! {"
+            keypress_delay_ms: 10
+            keymap:
+              - remap:
+                  C-b: [left, a]
+        "})
+        .unwrap();
+        config.keymap_table = build_keymap_table(&config.keymap);
+        let steps = parse_steps("C-b 5ms x").unwrap();
+        let actions: Vec<String> = simulate_steps(&config, None, &steps)
+            .unwrap()
+            .iter()
+            .filter(
+                |(_, action)| !matches!(action, Action::KeyEvent(event) if event.code() == Key::KEY_LEFTCTRL.code()),
+            )
+            .map(|(time, action)| format!("{} {:?}", time.as_millis(), action))
+            .collect();
+        // Each delay holds back the following actions, including ones for keys typed during the delay
+        assert_eq!(
+            vec![
+                "0 KeyEvent(KeyEvent { key: KEY_LEFT, value: Press })",
+                "0 KeyEvent(KeyEvent { key: KEY_LEFT, value: Release })",
+                "0 Delay(10ms)",
+                "10 KeyEvent(KeyEvent { key: KEY_A, value: Press })",
+                "10 KeyEvent(KeyEvent { key: KEY_A, value: Release })",
+                "10 Delay(10ms)",
+                "20 KeyEvent(KeyEvent { key: KEY_B, value: Release })",
+                "20 KeyEvent(KeyEvent { key: KEY_X, value: Press })",
+                "20 KeyEvent(KeyEvent { key: KEY_X, value: Release })",
+            ],
+            actions
+        );
+    }
+}
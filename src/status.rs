@@ -0,0 +1,179 @@
+use serde::Serialize;
+use std::fs;
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+// What status bars want to show. Published when any of it changes.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Status {
+    pub mode: String,
+    // Waiting for the next key of a nested remap
+    pub override_remap: bool,
+    pub mark_set: bool,
+    // The application seen when `application` was last checked
+    pub application: Option<String>,
+    pub paused: bool,
+}
+
+// A line of JSON sent to subscribers, e.g. `{"event":"mode","mode":"insert"}`
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum StatusEvent {
+    // The whole status, sent when subscribed
+    State(Status),
+    Mode { mode: String },
+    Override { active: bool },
+    Mark { set: bool },
+    Application { application: Option<String> },
+    Paused { paused: bool },
+}
+
+pub struct StatusPublisher {
+    status: Option<Status>,
+    // Rewritten with the whole status on every change
+    state_file: Option<PathBuf>,
+    // Connections of `xremap ctl subscribe`
+    subscribers: Vec<UnixStream>,
+}
+
+impl StatusPublisher {
+    pub fn new(state_file: Option<PathBuf>) -> StatusPublisher {
+        StatusPublisher {
+            status: None,
+            state_file,
+            subscribers: vec![],
+        }
+    }
+
+    pub fn publish(&mut self, status: Status) {
+        if self.status.as_ref() == Some(&status) {
+            return;
+        }
+        let events = status_events(self.status.as_ref(), &status);
+        for event in &events {
+            send_event(&mut self.subscribers, event);
+        }
+        if let Some(path) = &self.state_file {
+            if let Err(e) = write_state_file(path, &status) {
+                println!("Failed to write {}: {}", path.display(), e);
+            }
+        }
+        self.status = Some(status);
+    }
+
+    pub fn subscribe(&mut self, subscriber: UnixStream) {
+        // Don't let a stuck subscriber block remapping. It's dropped when its buffer is full.
+        if subscriber.set_nonblocking(true).is_err() {
+            return;
+        }
+        let status = self.status.clone().unwrap_or_default();
+        let mut subscribers = vec![subscriber];
+        send_event(&mut subscribers, &StatusEvent::State(status));
+        self.subscribers.extend(subscribers);
+    }
+}
+
+fn send_event(subscribers: &mut Vec<UnixStream>, event: &StatusEvent) {
+    let line = match serde_json::to_string(event) {
+        Ok(json) => json + "\n",
+        Err(_) => return,
+    };
+    // Disconnected subscribers are found on write
+    subscribers.retain_mut(|subscriber| subscriber.write_all(line.as_bytes()).is_ok());
+}
+
+fn status_events(old: Option<&Status>, new: &Status) -> Vec<StatusEvent> {
+    let old = match old {
+        Some(old) => old,
+        None => return vec![StatusEvent::State(new.clone())],
+    };
+    let mut events = vec![];
+    if old.mode != new.mode {
+        events.push(StatusEvent::Mode { mode: new.mode.clone() });
+    }
+    if old.override_remap != new.override_remap {
+        events.push(StatusEvent::Override {
+            active: new.override_remap,
+        });
+    }
+    if old.mark_set != new.mark_set {
+        events.push(StatusEvent::Mark { set: new.mark_set });
+    }
+    if old.application != new.application {
+        events.push(StatusEvent::Application {
+            application: new.application.clone(),
+        });
+    }
+    if old.paused != new.paused {
+        events.push(StatusEvent::Paused { paused: new.paused });
+    }
+    events
+}
+
+// Write to a temporary file and rename it so that readers never see a partial file
+fn write_state_file(path: &Path, status: &Status) -> std::io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, serde_json::to_string(status)? + "\n")?;
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+
+    #[test]
+    fn test_status_events() {
+        let old = Status {
+            mode: "normal".into(),
+            ..Status::default()
+        };
+        let new = Status {
+            mode: "insert".into(),
+            override_remap: true,
+            ..Status::default()
+        };
+        assert_eq!(vec![StatusEvent::State(old.clone())], status_events(None, &old));
+        assert_eq!(Vec::<StatusEvent>::new(), status_events(Some(&old), &old));
+        assert_eq!(
+            vec![
+                StatusEvent::Mode { mode: "insert".into() },
+                StatusEvent::Override { active: true }
+            ],
+            status_events(Some(&old), &new)
+        );
+    }
+
+    #[test]
+    fn test_publish() {
+        let state_file = std::env::temp_dir().join(format!("xremap-test-{}.json", std::process::id()));
+        let mut publisher = StatusPublisher::new(Some(state_file.clone()));
+        publisher.publish(Status {
+            mode: "normal".into(),
+            ..Status::default()
+        });
+        let (subscriber, client) = UnixStream::pair().unwrap();
+        publisher.subscribe(subscriber);
+        publisher.publish(Status {
+            mode: "insert".into(),
+            ..Status::default()
+        });
+        drop(publisher);
+
+        let lines: Vec<String> = BufReader::new(client).lines().map(Result::unwrap).collect();
+        assert_eq!(
+            vec![
+                r#"{"event":"state","mode":"normal","override_remap":false,"mark_set":false,"application":null,"paused":false}"#,
+                r#"{"event":"mode","mode":"insert"}"#,
+            ],
+            lines
+        );
+        assert_eq!(
+            "{\"mode\":\"insert\",\"override_remap\":false,\"mark_set\":false,\"application\":null,\"paused\":false}\n",
+            fs::read_to_string(&state_file).unwrap()
+        );
+        fs::remove_file(state_file).unwrap();
+    }
+}
@@ -2,29 +2,25 @@ use evdev::EventType;
 use evdev::InputEvent;
 use evdev::Key;
 use indoc::indoc;
-use nix::sys::timerfd::{ClockId, TimerFd, TimerFlags};
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
 use std::time::Duration;
 
-use crate::client::{Client, WMClient};
+use crate::clock::Clock;
 use crate::{
     action::Action,
     config::{keymap::build_keymap_table, Config},
+    device::InputDeviceInfo,
     event::{Event, KeyEvent, KeyValue, RelativeEvent},
-    event_handler::EventHandler,
+    simulate::Simulator,
 };
 
-struct StaticClient {
-    current_application: Option<String>,
-}
-
-impl Client for StaticClient {
-    fn supported(&mut self) -> bool {
-        true
-    }
-
-    fn current_application(&mut self) -> Option<String> {
-        self.current_application.clone()
-    }
+fn get_input_device_info() -> Rc<InputDeviceInfo> {
+    Rc::new(InputDeviceInfo {
+        name: "Some Device".to_string(),
+        path: PathBuf::from("/dev/input/event0"),
+    })
 }
 
 #[test]
@@ -36,10 +32,10 @@ fn test_basic_modmap() {
               a: b
         "},
         vec![
-            Event::KeyEvent(KeyEvent::new(Key::KEY_A, KeyValue::Press)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_A, KeyValue::Release)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_B, KeyValue::Press)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_B, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_A, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_B, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_B, KeyValue::Release)),
         ],
         vec![
             Action::KeyEvent(KeyEvent::new(Key::KEY_B, KeyValue::Press)),
@@ -93,7 +89,10 @@ fn test_relative_events() {
           - remap:
               XRIGHTCURSOR: b
         "},
-        vec![Event::RelativeEvent(RelativeEvent::new_with(_REL_X, _POSITIVE))],
+        vec![Event::RelativeEvent(
+            get_input_device_info(),
+            RelativeEvent::new_with(_REL_X, _POSITIVE),
+        )],
         vec![
             Action::KeyEvent(KeyEvent::new(Key::KEY_B, KeyValue::Press)),
             Action::KeyEvent(KeyEvent::new(Key::KEY_B, KeyValue::Release)),
@@ -122,8 +121,8 @@ fn test_mouse_movement_event_accumulation() {
     assert_actions(
         indoc! {""},
         vec![
-            Event::RelativeEvent(RelativeEvent::new_with(_REL_X, _POSITIVE)),
-            Event::RelativeEvent(RelativeEvent::new_with(_REL_Y, _POSITIVE)),
+            Event::RelativeEvent(get_input_device_info(), RelativeEvent::new_with(_REL_X, _POSITIVE)),
+            Event::RelativeEvent(get_input_device_info(), RelativeEvent::new_with(_REL_Y, _POSITIVE)),
         ],
         vec![Action::MouseMovementEventCollection(vec![
             RelativeEvent::new_with(_REL_X, _POSITIVE),
@@ -251,8 +250,8 @@ fn test_interleave_modifiers() {
               M-f: C-right
         "},
         vec![
-            Event::KeyEvent(KeyEvent::new(Key::KEY_LEFTALT, KeyValue::Press)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_F, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTALT, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_F, KeyValue::Press)),
         ],
         vec![
             Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTALT, KeyValue::Press)),
@@ -277,9 +276,9 @@ fn test_exact_match_true() {
               M-f: C-right
         "},
         vec![
-            Event::KeyEvent(KeyEvent::new(Key::KEY_LEFTALT, KeyValue::Press)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_F, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTALT, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_F, KeyValue::Press)),
         ],
         vec![
             Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTALT, KeyValue::Press)),
@@ -299,9 +298,9 @@ fn test_exact_match_false() {
               M-f: C-right
         "},
         vec![
-            Event::KeyEvent(KeyEvent::new(Key::KEY_LEFTALT, KeyValue::Press)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_F, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTALT, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_F, KeyValue::Press)),
         ],
         vec![
             Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTALT, KeyValue::Press)),
@@ -326,9 +325,9 @@ fn test_exact_match_default() {
               M-f: C-right
         "},
         vec![
-            Event::KeyEvent(KeyEvent::new(Key::KEY_LEFTALT, KeyValue::Press)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_F, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTALT, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_F, KeyValue::Press)),
         ],
         vec![
             Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTALT, KeyValue::Press)),
@@ -356,12 +355,12 @@ fn test_exact_match_true_nested() {
                   h: C-a
         "},
         vec![
-            Event::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_X, KeyValue::Press)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_X, KeyValue::Release)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_H, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_X, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_X, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_H, KeyValue::Press)),
         ],
         vec![
             Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
@@ -385,12 +384,12 @@ fn test_exact_match_false_nested() {
                   h: C-a
         "},
         vec![
-            Event::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_X, KeyValue::Press)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_X, KeyValue::Release)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_H, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_X, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_X, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_H, KeyValue::Press)),
         ],
         vec![
             Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
@@ -424,7 +423,10 @@ fn test_application_override() {
 
     assert_actions(
         config,
-        vec![Event::KeyEvent(KeyEvent::new(Key::KEY_A, KeyValue::Press))],
+        vec![Event::KeyEvent(
+            get_input_device_info(),
+            KeyEvent::new(Key::KEY_A, KeyValue::Press),
+        )],
         vec![
             Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
             Action::KeyEvent(KeyEvent::new(Key::KEY_B, KeyValue::Press)),
@@ -437,7 +439,42 @@ fn test_application_override() {
     assert_actions_with_current_application(
         config,
         Some(String::from("firefox")),
-        vec![Event::KeyEvent(KeyEvent::new(Key::KEY_A, KeyValue::Press))],
+        vec![Event::KeyEvent(
+            get_input_device_info(),
+            KeyEvent::new(Key::KEY_A, KeyValue::Press),
+        )],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_C, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_C, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
+        ],
+    );
+}
+
+#[test]
+fn test_window_override() {
+    let config = indoc! {"
+        keymap:
+          - name: jira
+            window:
+              only: /Jira/
+            remap:
+              a: C-c
+
+          - name: generic
+            remap:
+              a: C-b
+    "};
+
+    assert_actions_with_current_window(
+        config,
+        Some(String::from("Dashboard - Jira - Mozilla Firefox")),
+        vec![Event::KeyEvent(
+            get_input_device_info(),
+            KeyEvent::new(Key::KEY_A, KeyValue::Press),
+        )],
         vec![
             Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
             Action::KeyEvent(KeyEvent::new(Key::KEY_C, KeyValue::Press)),
@@ -446,6 +483,263 @@ fn test_application_override() {
             Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
         ],
     );
+
+    assert_actions_with_current_window(
+        config,
+        Some(String::from("Google Docs - Mozilla Firefox")),
+        vec![Event::KeyEvent(
+            get_input_device_info(),
+            KeyEvent::new(Key::KEY_A, KeyValue::Press),
+        )],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_B, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_B, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
+        ],
+    );
+}
+
+#[test]
+fn test_layer() {
+    let config = indoc! {"
+        keymap:
+          - remap:
+              CapsLock: { layer_hold: nav }
+              Super-n: { layer_toggle: num }
+              Super-o: { layer_oneshot: nav }
+          - layer: nav
+            remap:
+              h: left
+              j: down
+          - layer: num
+            remap:
+              j: KEY_4
+    "};
+
+    // layer_hold is released with the key, and falls through to the layers below it
+    assert_actions(
+        config,
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_CAPSLOCK, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_H, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_K, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_CAPSLOCK, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_H, KeyValue::Press)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFT, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFT, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_K, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_CAPSLOCK, KeyValue::Release)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_H, KeyValue::Press)),
+        ],
+    );
+
+    // The top layer takes precedence until it's toggled off
+    assert_actions(
+        config,
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTMETA, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_N, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTMETA, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_CAPSLOCK, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_J, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_CAPSLOCK, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTMETA, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_N, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTMETA, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_J, KeyValue::Press)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTMETA, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTMETA, KeyValue::Release)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_DOWN, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_DOWN, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_CAPSLOCK, KeyValue::Release)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTMETA, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTMETA, KeyValue::Release)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_J, KeyValue::Press)),
+        ],
+    );
+
+    // layer_oneshot is active only for the next key press
+    assert_actions(
+        config,
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTMETA, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_O, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTMETA, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_H, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_H, KeyValue::Press)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTMETA, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTMETA, KeyValue::Release)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFT, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFT, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_H, KeyValue::Press)),
+        ],
+    );
+}
+
+#[test]
+fn test_layer_key_repeat() {
+    let config = indoc! {"
+        keymap:
+          - remap:
+              Super-n: { layer_toggle: num }
+              Super-o: { layer_oneshot: nav }
+          - layer: nav
+            remap:
+              h: left
+          - layer: num
+            remap:
+              j: KEY_4
+    "};
+
+    // Holding a layer_toggle key toggles the layer only once
+    assert_actions(
+        config,
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTMETA, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_N, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_N, KeyValue::Repeat)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_N, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTMETA, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_J, KeyValue::Press)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTMETA, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_N, KeyValue::Release)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTMETA, KeyValue::Release)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_4, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_4, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+        ],
+    );
+
+    // Holding a layer_oneshot key doesn't stack the layer
+    assert_actions(
+        config,
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTMETA, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_O, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_O, KeyValue::Repeat)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_O, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTMETA, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_H, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_H, KeyValue::Press)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTMETA, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_O, KeyValue::Release)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTMETA, KeyValue::Release)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFT, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFT, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_H, KeyValue::Press)),
+        ],
+    );
+}
+
+#[test]
+fn test_when_condition() {
+    let config = indoc! {"
+        keymap:
+          - when:
+              all:
+                - mode: default
+                - not: { application: Alacritty }
+                - any:
+                    - device: Ergonomic
+                    - held: Shift_L
+            remap:
+              a: b
+    "};
+
+    assert_actions_with_current_application(
+        config,
+        Some(String::from("Firefox")),
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_B, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_B, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+        ],
+    );
+
+    assert_actions_with_current_application(
+        config,
+        Some(String::from("Alacritty")),
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+        ],
+    );
+}
+
+#[test]
+fn test_device_override() {
+    let config = indoc! {"
+        modmap:
+          - name: laptop
+            device:
+              only: Some Device
+            remap:
+              CapsLock: Ctrl_L
+
+        keymap:
+          - name: external
+            device:
+              not: [event0]
+            remap:
+              a: b
+    "};
+    let external = Rc::new(InputDeviceInfo {
+        name: "Ergonomic Keyboard".to_string(),
+        path: PathBuf::from("/dev/input/event1"),
+    });
+
+    assert_actions(
+        config,
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_CAPSLOCK, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+        ],
+    );
+
+    assert_actions(
+        config,
+        vec![
+            Event::KeyEvent(external.clone(), KeyEvent::new(Key::KEY_CAPSLOCK, KeyValue::Press)),
+            Event::KeyEvent(external, KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_CAPSLOCK, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_B, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_B, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+        ],
+    );
 }
 
 #[test]
@@ -465,11 +759,11 @@ fn test_merge_remaps() {
     assert_actions(
         config,
         vec![
-            Event::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_X, KeyValue::Press)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_X, KeyValue::Release)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_H, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_X, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_X, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_H, KeyValue::Press)),
         ],
         vec![
             Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
@@ -486,11 +780,11 @@ fn test_merge_remaps() {
     assert_actions(
         config,
         vec![
-            Event::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_X, KeyValue::Press)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_X, KeyValue::Release)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_K, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_X, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_X, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_K, KeyValue::Press)),
         ],
         vec![
             Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
@@ -523,11 +817,11 @@ fn test_merge_remaps_with_override() {
     assert_actions(
         config,
         vec![
-            Event::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_X, KeyValue::Press)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_X, KeyValue::Release)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_H, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_X, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_X, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_H, KeyValue::Press)),
         ],
         vec![
             Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
@@ -544,11 +838,11 @@ fn test_merge_remaps_with_override() {
     assert_actions(
         config,
         vec![
-            Event::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_X, KeyValue::Press)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_X, KeyValue::Release)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
-            Event::KeyEvent(KeyEvent::new(Key::KEY_C, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_X, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_X, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_C, KeyValue::Press)),
         ],
         vec![
             Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
@@ -563,28 +857,902 @@ fn test_merge_remaps_with_override() {
     )
 }
 
-fn assert_actions(config_yaml: &str, events: Vec<Event>, actions: Vec<Action>) {
-    assert_actions_with_current_application(config_yaml, None, events, actions);
+#[test]
+fn test_tap_dance() {
+    let config = indoc! {"
+        modmap:
+          - remap:
+              Esc:
+                tap_dance:
+                  taps: [Esc, C-l]
+                  hold: Ctrl_L
+    "};
+
+    // Double tap
+    assert_actions(
+        config,
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_ESC, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_ESC, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_ESC, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_ESC, KeyValue::Release)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_L, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_L, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
+        ],
+    );
+
+    // Single tap interrupted by another key
+    assert_actions(
+        config,
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_ESC, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_ESC, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_ESC, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_ESC, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+        ],
+    );
 }
 
-fn assert_actions_with_current_application(
-    config_yaml: &str,
-    current_application: Option<String>,
-    events: Vec<Event>,
-    actions: Vec<Action>,
-) {
-    let timer = TimerFd::new(ClockId::CLOCK_MONOTONIC, TimerFlags::empty()).unwrap();
-    let mut config: Config = serde_yaml::from_str(config_yaml).unwrap();
-    config.keymap_table = build_keymap_table(&config.keymap);
-    let mut event_handler = EventHandler::new(
-        timer,
-        "default",
-        Duration::from_micros(0),
-        WMClient::new("static", Box::new(StaticClient { current_application })),
+#[test]
+fn test_tap_dance_timeout() {
+    let config = indoc! {"
+        modmap:
+          - remap:
+              Esc:
+                tap_dance:
+                  taps: [Esc, C-l]
+                  hold: Ctrl_L
+                  timeout_millis: 0
+    "};
+
+    // Single tap
+    assert_actions(
+        config,
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_ESC, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_ESC, KeyValue::Release)),
+            Event::OverrideTimeout,
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_ESC, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_ESC, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+        ],
+    );
+
+    // Hold
+    assert_actions(
+        config,
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_ESC, KeyValue::Press)),
+            Event::OverrideTimeout,
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_ESC, KeyValue::Repeat)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_ESC, KeyValue::Release)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Repeat)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
+        ],
+    );
+}
+
+#[test]
+fn test_tap_dance_hold_actions() {
+    assert_actions(
+        indoc! {"
+        modmap:
+          - remap:
+              Esc:
+                tap_dance:
+                  taps: [Esc]
+                  hold: C-l
+                  timeout_millis: 0
+        "},
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_ESC, KeyValue::Press)),
+            Event::OverrideTimeout,
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_ESC, KeyValue::Repeat)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_ESC, KeyValue::Release)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_L, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_L, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
+        ],
     );
-    let mut actual: Vec<Action> = vec![];
+}
 
-    actual.append(&mut event_handler.on_events(&events, &config).unwrap());
+#[test]
+fn test_one_shot() {
+    let config = indoc! {"
+        modmap:
+          - remap:
+              Shift_L:
+                one_shot: Shift_L
+    "};
 
-    assert_eq!(format!("{:?}", actions), format!("{:?}", actual));
+    // Tap
+    assert_actions(
+        config,
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_A, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_B, KeyValue::Press)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Release)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_A, KeyValue::Release)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_B, KeyValue::Press)),
+        ],
+    );
+
+    // Hold
+    assert_actions(
+        config,
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_A, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_B, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Release)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_A, KeyValue::Release)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_B, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Release)),
+        ],
+    );
+}
+
+#[test]
+fn test_one_shot_timeout() {
+    assert_actions(
+        indoc! {"
+        modmap:
+          - remap:
+              Shift_L:
+                one_shot: Shift_L
+                timeout_millis: 0
+        "},
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Release)),
+            Event::OverrideTimeout,
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Release)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+        ],
+    );
+}
+
+#[test]
+fn test_combo() {
+    let config = indoc! {"
+        combo:
+          - keys: [j, k]
+            actions: Esc
+    "};
+
+    assert_actions(
+        config,
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_J, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_K, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_K, KeyValue::Repeat)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_J, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_K, KeyValue::Release)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_ESC, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_ESC, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+        ],
+    );
+
+    // Replay the keys if they're not pressed together
+    assert_actions(
+        config,
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_J, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_J, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_K, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_J, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_J, KeyValue::Release)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_K, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+        ],
+    );
+}
+
+#[test]
+fn test_combo_subset() {
+    let config = indoc! {"
+        combo:
+          - keys: [j, k]
+            actions: Esc
+          - keys: [j, k, l]
+            actions: Enter
+    "};
+
+    // The longer combo isn't shadowed by the shorter one
+    assert_actions(
+        config,
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_J, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_K, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_L, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_J, KeyValue::Release)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_ENTER, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_ENTER, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+        ],
+    );
+
+    // The shorter combo is dispatched when one of its keys is released
+    assert_actions(
+        config,
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_J, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_K, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_J, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_K, KeyValue::Release)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_ESC, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_ESC, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+        ],
+    );
+
+    // ... when another key is pressed, before the key
+    assert_actions(
+        config,
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_J, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_K, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_ESC, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_ESC, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+        ],
+    );
+
+    // ... or on the timeout
+    let mut config: Config = serde_yaml::from_str(config).unwrap();
+    config.keymap_table = build_keymap_table(&config.keymap);
+    let mut simulator = Simulator::new(&config).unwrap();
+    let clock = simulator.clock().clone();
+    let handler = simulator.handler();
+    let events = vec![
+        Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_K, KeyValue::Press)),
+        Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_J, KeyValue::Press)),
+    ];
+    assert_eq!("[]", format!("{:?}", handler.on_events(&events, &config).unwrap()));
+    clock.set_elapsed(Duration::from_millis(50));
+    assert_eq!(
+        format!(
+            "{:?}",
+            vec![
+                Action::KeyEvent(KeyEvent::new(Key::KEY_ESC, KeyValue::Press)),
+                Action::KeyEvent(KeyEvent::new(Key::KEY_ESC, KeyValue::Release)),
+                Action::Delay(Duration::from_nanos(0)),
+            ]
+        ),
+        format!("{:?}", handler.on_events(&vec![Event::OverrideTimeout], &config).unwrap())
+    );
+}
+
+#[test]
+fn test_combo_timeout() {
+    assert_actions(
+        indoc! {"
+        combo:
+          - keys: [j, k]
+            actions: Esc
+            timeout_millis: 0
+        "},
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_J, KeyValue::Press)),
+            Event::OverrideTimeout,
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_J, KeyValue::Release)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_J, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_J, KeyValue::Release)),
+        ],
+    );
+}
+
+#[test]
+fn test_leader() {
+    let config = indoc! {"
+        leader:
+          key: C-Space
+          sequences:
+            leader g s: C-s
+            leader g: C-g
+    "};
+
+    assert_actions(
+        config,
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_SPACE, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_G, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_S, KeyValue::Press)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_S, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_S, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
+        ],
+    );
+
+    // A complete sequence is dispatched when the next key doesn't match a longer one
+    assert_actions(
+        config,
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_SPACE, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_G, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_X, KeyValue::Press)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_G, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_G, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_X, KeyValue::Press)),
+        ],
+    );
+
+    // An incomplete sequence is cancelled on a mismatch
+    assert_actions(
+        config,
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_SPACE, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_X, KeyValue::Press)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_X, KeyValue::Press)),
+        ],
+    );
+}
+
+#[test]
+fn test_leader_replay() {
+    let config = indoc! {"
+        leader:
+          key: C-Space
+          sequences:
+            leader g s: C-s
+            leader g: C-g
+          timeout_millis: 0
+          replay: true
+    "};
+
+    assert_actions(
+        config,
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_SPACE, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_X, KeyValue::Press)),
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_SPACE, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_SPACE, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_X, KeyValue::Press)),
+        ],
+    );
+
+    // The timeout dispatches a complete sequence and replays an incomplete one
+    assert_actions(
+        config,
+        vec![
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_SPACE, KeyValue::Press)),
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_G, KeyValue::Press)),
+            Event::OverrideTimeout,
+            Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_SPACE, KeyValue::Press)),
+            Event::OverrideTimeout,
+        ],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_G, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_G, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_SPACE, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_SPACE, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+        ],
+    );
+}
+
+#[test]
+fn test_type() {
+    assert_actions(
+        indoc! {"
+        keymap:
+          - remap:
+              F1: { type: Hi→ }
+        "},
+        vec![Event::KeyEvent(
+            get_input_device_info(),
+            KeyEvent::new(Key::KEY_F1, KeyValue::Press),
+        )],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_H, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_H, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Release)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_I, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_I, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_U, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_U, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Release)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_2, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_2, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_1, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_1, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_9, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_9, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_2, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_2, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_SPACE, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_SPACE, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+        ],
+    );
+}
+
+#[test]
+fn test_type_compose() {
+    let compose_file = std::env::temp_dir().join(format!("xremap-test-compose-{}", std::process::id()));
+    fs::write(&compose_file, "<Multi_key> <minus> <greater> : \"→\" U2192 # RIGHTWARDS ARROW\n").unwrap();
+    let config = format!(
+        indoc! {"
+        unicode_input:
+          compose: Alt_R
+          compose_file: {}
+        keymap:
+          - remap:
+              F1: {{ type: → }}
+        "},
+        compose_file.display()
+    );
+    assert_actions(
+        &config,
+        vec![Event::KeyEvent(
+            get_input_device_info(),
+            KeyEvent::new(Key::KEY_F1, KeyValue::Press),
+        )],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_RIGHTALT, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_RIGHTALT, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_MINUS, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_MINUS, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_DOT, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_DOT, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Release)),
+        ],
+    );
+    fs::remove_file(&compose_file).unwrap();
+}
+
+#[test]
+fn test_sleep_and_repeat() {
+    assert_actions(
+        indoc! {"
+        keymap:
+          - remap:
+              f1:
+                - a
+                - sleep: 50
+                - repeat:
+                    times: 2
+                    actions: [b, { sleep: 10 }]
+        "},
+        vec![Event::KeyEvent(
+            get_input_device_info(),
+            KeyEvent::new(Key::KEY_F1, KeyValue::Press),
+        )],
+        vec![
+            Action::KeyEvent(KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_A, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::Delay(Duration::from_millis(50)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_B, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_B, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::Delay(Duration::from_millis(10)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_B, KeyValue::Press)),
+            Action::KeyEvent(KeyEvent::new(Key::KEY_B, KeyValue::Release)),
+            Action::Delay(Duration::from_nanos(0)),
+            Action::Delay(Duration::from_millis(10)),
+        ],
+    )
+}
+
+#[test]
+fn test_release_all() {
+    let config: Config = serde_yaml::from_str(indoc! {"
+        modmap:
+          - remap:
+              CapsLock:
+                held: Control_L
+                alone: Esc
+                alone_timeout_millis: 0
+              A: B
+    "})
+    .unwrap();
+    let mut simulator = Simulator::new(&config).unwrap();
+    let handler = simulator.handler();
+    let events = vec![
+        Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
+        Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+        Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_CAPSLOCK, KeyValue::Press)),
+    ];
+    handler.on_events(&events, &config).unwrap();
+
+    // The multi-purpose key hasn't pressed anything, so it's just forgotten
+    assert_eq!(
+        format!(
+            "{:?}",
+            vec![
+                Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Release)),
+                Action::KeyEvent(KeyEvent::new(Key::KEY_B, KeyValue::Release)),
+            ]
+        ),
+        format!("{:?}", handler.release_all())
+    );
+    assert_eq!("[]", format!("{:?}", handler.release_all()));
+}
+
+#[test]
+fn test_config_reload() {
+    let old_config: Config = serde_yaml::from_str(indoc! {"
+        keymap:
+          - mode: default
+            remap:
+              Esc: { set_mode: normal }
+          - mode: normal
+            remap:
+              C-x:
+                remap:
+                  C-c: C-q
+    "})
+    .unwrap();
+    let new_config: Config = serde_yaml::from_str(indoc! {"
+        default_mode: insert
+        keypress_delay_ms: 20
+        keymap:
+          - mode: insert
+            remap:
+              C-c: C-q
+              F1: F2
+    "})
+    .unwrap();
+    let mut config = old_config;
+    config.keymap_table = build_keymap_table(&config.keymap);
+    let mut simulator = Simulator::new(&config).unwrap();
+    let handler = simulator.handler();
+    let events = vec![
+        Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_ESC, KeyValue::Press)),
+        Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_ESC, KeyValue::Release)),
+        Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+        Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_X, KeyValue::Press)),
+    ];
+    handler.on_events(&events, &config).unwrap();
+    assert_eq!("normal", handler.mode());
+    assert!(handler.status(false).override_remap);
+
+    // The nested remap is cancelled, and the mode that no longer exists falls back to default_mode
+    let mut config = new_config;
+    config.keymap_table = build_keymap_table(&config.keymap);
+    assert_eq!(
+        format!("{:?}", vec![Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release))]),
+        format!("{:?}", handler.release_all())
+    );
+    handler.on_config_reload(&config);
+    assert_eq!("insert", handler.mode());
+    assert!(!handler.status(false).override_remap);
+
+    // keypress_delay_ms of the new config is used
+    let events = vec![Event::KeyEvent(
+        get_input_device_info(),
+        KeyEvent::new(Key::KEY_F1, KeyValue::Press),
+    )];
+    assert_eq!(
+        format!(
+            "{:?}",
+            vec![
+                Action::KeyEvent(KeyEvent::new(Key::KEY_F2, KeyValue::Press)),
+                Action::KeyEvent(KeyEvent::new(Key::KEY_F2, KeyValue::Release)),
+                Action::Delay(Duration::from_millis(20)),
+            ]
+        ),
+        format!("{:?}", handler.on_events(&events, &config).unwrap())
+    );
+}
+
+#[test]
+fn test_emergency_chord() {
+    let config: Config = serde_yaml::from_str(indoc! {"
+        modmap:
+          - remap:
+              CapsLock: Esc
+    "})
+    .unwrap();
+    let new_simulator = || Simulator::new(&config).unwrap();
+    let press = |key| Event::KeyEvent(get_input_device_info(), KeyEvent::new(key, KeyValue::Press));
+
+    // Everything pressed on the output device is released, and devices should be ungrabbed
+    let mut simulator = new_simulator();
+    let (clock, timer) = (simulator.clock().clone(), simulator.timer().clone());
+    let handler = simulator.handler();
+    let events = vec![
+        press(Key::KEY_A),
+        press(Key::KEY_LEFTCTRL),
+        press(Key::KEY_RIGHTALT),
+        press(Key::KEY_LEFTSHIFT),
+        press(Key::KEY_ESC),
+    ];
+    let mut actual = handler.on_events(&events, &config).unwrap();
+    assert_eq!(Some(clock.now() + Duration::from_secs(2)), timer.deadline());
+    clock.set_elapsed(Duration::from_millis(1999));
+    actual.append(&mut handler.on_events(&vec![Event::OverrideTimeout], &config).unwrap());
+    assert!(!handler.take_emergency());
+    clock.set_elapsed(Duration::from_secs(2));
+    actual.append(&mut handler.on_events(&vec![Event::OverrideTimeout], &config).unwrap());
+    let actions = vec![
+        Action::KeyEvent(KeyEvent::new(Key::KEY_A, KeyValue::Press)),
+        Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+        Action::KeyEvent(KeyEvent::new(Key::KEY_RIGHTALT, KeyValue::Press)),
+        Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Press)),
+        Action::KeyEvent(KeyEvent::new(Key::KEY_ESC, KeyValue::Press)),
+        Action::KeyEvent(KeyEvent::new(Key::KEY_ESC, KeyValue::Release)),
+        Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
+        Action::KeyEvent(KeyEvent::new(Key::KEY_A, KeyValue::Release)),
+        Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTSHIFT, KeyValue::Release)),
+        Action::KeyEvent(KeyEvent::new(Key::KEY_RIGHTALT, KeyValue::Release)),
+    ];
+    assert_eq!(format!("{:?}", actions), format!("{:?}", actual));
+    assert!(handler.take_emergency());
+    assert_eq!(None, timer.deadline());
+    assert!(!handler.take_emergency());
+
+    // It's detected before modmap, so a remapped CapsLock doesn't count as Esc
+    let mut simulator = new_simulator();
+    let clock = simulator.clock().clone();
+    let handler = simulator.handler();
+    let events = vec![
+        press(Key::KEY_LEFTCTRL),
+        press(Key::KEY_LEFTALT),
+        press(Key::KEY_LEFTSHIFT),
+        press(Key::KEY_CAPSLOCK),
+    ];
+    handler.on_events(&events, &config).unwrap();
+    clock.set_elapsed(Duration::from_secs(2));
+    handler.on_events(&vec![Event::OverrideTimeout], &config).unwrap();
+    assert!(!handler.take_emergency());
+
+    // Releasing any key of the chord before the timeout cancels it
+    let mut simulator = new_simulator();
+    let (clock, timer) = (simulator.clock().clone(), simulator.timer().clone());
+    let handler = simulator.handler();
+    let events = vec![
+        press(Key::KEY_LEFTCTRL),
+        press(Key::KEY_LEFTALT),
+        press(Key::KEY_LEFTSHIFT),
+        press(Key::KEY_ESC),
+        Event::KeyEvent(get_input_device_info(), KeyEvent::new(Key::KEY_LEFTALT, KeyValue::Release)),
+    ];
+    handler.on_events(&events, &config).unwrap();
+    assert_eq!(None, timer.deadline());
+    clock.set_elapsed(Duration::from_secs(2));
+    handler.on_events(&vec![Event::OverrideTimeout], &config).unwrap();
+    assert!(!handler.take_emergency());
+}
+
+#[test]
+fn test_alone_timeout_exact_time() {
+    let config: Config = serde_yaml::from_str(indoc! {"
+        modmap:
+          - remap:
+              CapsLock:
+                held: Control_L
+                alone: Esc
+                alone_timeout_millis: 500
+    "})
+    .unwrap();
+    let new_simulator = || Simulator::new(&config).unwrap();
+    let capslock = |value| {
+        vec![Event::KeyEvent(
+            get_input_device_info(),
+            KeyEvent::new(Key::KEY_CAPSLOCK, value),
+        )]
+    };
+
+    // Released 1ms before the timeout
+    let mut simulator = new_simulator();
+    let clock = simulator.clock().clone();
+    let handler = simulator.handler();
+    assert_eq!("[]", format!("{:?}", handler.on_events(&capslock(KeyValue::Press), &config).unwrap()));
+    clock.set_elapsed(Duration::from_millis(499));
+    assert_eq!(
+        format!(
+            "{:?}",
+            vec![
+                Action::KeyEvent(KeyEvent::new(Key::KEY_ESC, KeyValue::Press)),
+                Action::KeyEvent(KeyEvent::new(Key::KEY_ESC, KeyValue::Release)),
+            ]
+        ),
+        format!("{:?}", handler.on_events(&capslock(KeyValue::Release), &config).unwrap())
+    );
+
+    // Released right at the timeout
+    let mut simulator = new_simulator();
+    let clock = simulator.clock().clone();
+    let handler = simulator.handler();
+    handler.on_events(&capslock(KeyValue::Press), &config).unwrap();
+    clock.set_elapsed(Duration::from_millis(500));
+    assert_eq!(
+        format!(
+            "{:?}",
+            vec![
+                Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press)),
+                Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Release)),
+            ]
+        ),
+        format!("{:?}", handler.on_events(&capslock(KeyValue::Release), &config).unwrap())
+    );
+
+    // Key repeats press the held key once the timeout is reached
+    let mut simulator = new_simulator();
+    let clock = simulator.clock().clone();
+    let handler = simulator.handler();
+    handler.on_events(&capslock(KeyValue::Press), &config).unwrap();
+    clock.set_elapsed(Duration::from_millis(499));
+    assert_eq!("[]", format!("{:?}", handler.on_events(&capslock(KeyValue::Repeat), &config).unwrap()));
+    clock.set_elapsed(Duration::from_millis(500));
+    assert_eq!(
+        format!("{:?}", vec![Action::KeyEvent(KeyEvent::new(Key::KEY_LEFTCTRL, KeyValue::Press))]),
+        format!("{:?}", handler.on_events(&capslock(KeyValue::Repeat), &config).unwrap())
+    );
+}
+
+#[test]
+fn test_remap_timeout_exact_time() {
+    let mut config: Config = serde_yaml::from_str(indoc! {"
+        keymap:
+          - remap:
+              C-x:
+                remap:
+                  C-s: C-w
+                timeout_millis: 1000
+    "})
+    .unwrap();
+    config.keymap_table = build_keymap_table(&config.keymap);
+    let mut simulator = Simulator::new(&config).unwrap();
+    let (clock, timer) = (simulator.clock().clone(), simulator.timer().clone());
+    let handler = simulator.handler();
+    let key = |key, value| Event::KeyEvent(get_input_device_info(), KeyEvent::new(key, value));
+    let events = vec![
+        key(Key::KEY_LEFTCTRL, KeyValue::Press),
+        key(Key::KEY_X, KeyValue::Press),
+        key(Key::KEY_X, KeyValue::Release),
+        key(Key::KEY_LEFTCTRL, KeyValue::Release),
+    ];
+    handler.on_events(&events, &config).unwrap();
+    assert_eq!(Some(clock.now() + Duration::from_millis(1000)), timer.deadline());
+
+    // A timeout fired early is ignored
+    clock.set_elapsed(Duration::from_millis(999));
+    assert_eq!("[]", format!("{:?}", handler.on_events(&vec![Event::OverrideTimeout], &config).unwrap()));
+    clock.set_elapsed(Duration::from_millis(1000));
+    assert_eq!(
+        format!(
+            "{:?}",
+            vec![
+                Action::KeyEvent(KeyEvent::new(Key::KEY_X, KeyValue::Press)),
+                Action::KeyEvent(KeyEvent::new(Key::KEY_X, KeyValue::Release)),
+            ]
+        ),
+        format!("{:?}", handler.on_events(&vec![Event::OverrideTimeout], &config).unwrap())
+    );
+    assert_eq!(None, timer.deadline());
+}
+
+fn assert_actions(config_yaml: &str, events: Vec<Event>, actions: Vec<Action>) {
+    assert_actions_with_current_application(config_yaml, None, events, actions);
+}
+
+fn assert_actions_with_current_application(
+    config_yaml: &str,
+    current_application: Option<String>,
+    events: Vec<Event>,
+    actions: Vec<Action>,
+) {
+    assert_actions_with_client(config_yaml, current_application, None, events, actions);
+}
+
+fn assert_actions_with_current_window(
+    config_yaml: &str,
+    current_window: Option<String>,
+    events: Vec<Event>,
+    actions: Vec<Action>,
+) {
+    assert_actions_with_client(config_yaml, None, current_window, events, actions);
+}
+
+fn assert_actions_with_client(
+    config_yaml: &str,
+    current_application: Option<String>,
+    current_window: Option<String>,
+    events: Vec<Event>,
+    actions: Vec<Action>,
+) {
+    let mut config: Config = serde_yaml::from_str(config_yaml).unwrap();
+    config.keymap_table = build_keymap_table(&config.keymap);
+    let mut simulator = Simulator::new(&config).unwrap();
+    simulator.set_application(current_application);
+    simulator.set_window(current_window);
+    simulator.send(events).unwrap();
+
+    assert_eq!(format!("{:?}", actions), format!("{:?}", simulator.take_actions()));
 }